@@ -4,8 +4,27 @@ use std::hint::black_box;
 use uom::si::f64::*;
 use uom::si::length::light_year;
 
+fn options(
+    jump_distance: u16,
+    algorithm: evedatacore_route_planner::data::PathAlgorithm,
+) -> evedatacore_route_planner::data::PathOptions {
+    evedatacore_route_planner::data::PathOptions {
+        jump_distance,
+        optimize: evedatacore_route_planner::data::PathOptimize::Distance,
+        weights: Default::default(),
+        algorithm,
+        avoid: Default::default(),
+        ship: None,
+        tank: None,
+        limits: evedatacore_route_planner::astar::SearchLimits::with_timeout(
+            std::time::Duration::from_secs(300),
+        ),
+        unit: Default::default(),
+    }
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
-    let mut group = c.benchmark_group(format!("benches").as_str());
+    let mut group = c.benchmark_group("benches");
     group.warm_up_time(std::time::Duration::from_secs(1));
     group.measurement_time(std::time::Duration::from_secs(1));
     group.sample_size(10);
@@ -21,7 +40,7 @@ fn criterion_benchmark(c: &mut Criterion) {
     for distance_factor in 0..10 {
         let distance: u16 = 50 + distance_factor * 50;
         group.bench_with_input(
-            BenchmarkId::new("504ly", &distance),
+            BenchmarkId::new("504ly", distance),
             &distance,
             |b, distance| {
                 let start = star_map
@@ -31,14 +50,44 @@ fn criterion_benchmark(c: &mut Criterion) {
                     .get(&evedatacore_route_planner::tools::system_id_to_u16(30013956).unwrap())
                     .unwrap();
                 b.iter(|| {
+                    let options = options(
+                        black_box(*distance),
+                        evedatacore_route_planner::data::PathAlgorithm::Astar,
+                    );
                     let path = evedatacore_route_planner::path::calc_path(
                         &star_map,
                         &smart_gates_map,
                         start,
                         end,
+                        &options,
+                    );
+                })
+            },
+        );
+    }
+    for distance_factor in 0..10 {
+        let distance: u16 = 50 + distance_factor * 50;
+        group.bench_with_input(
+            BenchmarkId::new("504ly-bidirectional", distance),
+            &distance,
+            |b, distance| {
+                let start = star_map
+                    .get(&evedatacore_route_planner::tools::system_id_to_u16(30001573).unwrap())
+                    .unwrap();
+                let end = star_map
+                    .get(&evedatacore_route_planner::tools::system_id_to_u16(30013956).unwrap())
+                    .unwrap();
+                b.iter(|| {
+                    let options = options(
                         black_box(*distance),
-                        evedatacore_route_planner::data::PathOptimize::Distance,
-                        Some(300),
+                        evedatacore_route_planner::data::PathAlgorithm::Bidirectional,
+                    );
+                    let path = evedatacore_route_planner::path::calc_path(
+                        &star_map,
+                        &smart_gates_map,
+                        start,
+                        end,
+                        &options,
                     );
                 })
             },
@@ -47,7 +96,7 @@ fn criterion_benchmark(c: &mut Criterion) {
     for distance_factor in 0..10 {
         let distance: u16 = 50 + distance_factor * 50;
         group.bench_with_input(
-            BenchmarkId::new("4289ly", &distance),
+            BenchmarkId::new("4289ly", distance),
             &distance,
             |b, distance| {
                 let start = star_map
@@ -57,14 +106,16 @@ fn criterion_benchmark(c: &mut Criterion) {
                     .get(&evedatacore_route_planner::tools::system_id_to_u16(30020622).unwrap())
                     .unwrap();
                 b.iter(|| {
+                    let options = options(
+                        black_box(*distance),
+                        evedatacore_route_planner::data::PathAlgorithm::Astar,
+                    );
                     let path = evedatacore_route_planner::path::calc_path(
                         &star_map,
                         &smart_gates_map,
                         start,
                         end,
-                        black_box(*distance),
-                        evedatacore_route_planner::data::PathOptimize::Distance,
-                        Some(300),
+                        &options,
                     );
                 })
             },
@@ -73,7 +124,7 @@ fn criterion_benchmark(c: &mut Criterion) {
     for distance_factor in 0..10 {
         let distance: u16 = 50 + distance_factor * 50;
         group.bench_with_input(
-            BenchmarkId::new("4618ly", &distance),
+            BenchmarkId::new("4618ly", distance),
             &distance,
             |b, distance| {
                 let start = star_map
@@ -83,14 +134,16 @@ fn criterion_benchmark(c: &mut Criterion) {
                     .get(&evedatacore_route_planner::tools::system_id_to_u16(30022683).unwrap())
                     .unwrap();
                 b.iter(|| {
+                    let options = options(
+                        black_box(*distance),
+                        evedatacore_route_planner::data::PathAlgorithm::Astar,
+                    );
                     let path = evedatacore_route_planner::path::calc_path(
                         &star_map,
                         &smart_gates_map,
                         start,
                         end,
-                        black_box(*distance),
-                        evedatacore_route_planner::data::PathOptimize::Distance,
-                        Some(300),
+                        &options,
                     );
                 })
             },
@@ -99,7 +152,7 @@ fn criterion_benchmark(c: &mut Criterion) {
     for distance_factor in 0..10 {
         let distance: u16 = 50 + distance_factor * 50;
         group.bench_with_input(
-            BenchmarkId::new("7610ly", &distance),
+            BenchmarkId::new("7610ly", distance),
             &distance,
             |b, distance| {
                 let start = star_map
@@ -109,14 +162,16 @@ fn criterion_benchmark(c: &mut Criterion) {
                     .get(&evedatacore_route_planner::tools::system_id_to_u16(30022683).unwrap())
                     .unwrap();
                 b.iter(|| {
+                    let options = options(
+                        black_box(*distance),
+                        evedatacore_route_planner::data::PathAlgorithm::Astar,
+                    );
                     let path = evedatacore_route_planner::path::calc_path(
                         &star_map,
                         &smart_gates_map,
                         start,
                         end,
-                        black_box(*distance),
-                        evedatacore_route_planner::data::PathOptimize::Distance,
-                        Some(300),
+                        &options,
                     );
                 })
             },
@@ -1,7 +1,8 @@
 use std::collections::HashMap;
-use std::time::Instant;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{Duration, Instant};
 
-use bincode;
 use clap::{Parser, Subcommand};
 use indicatif::ProgressIterator;
 use log::{info, warn};
@@ -12,11 +13,19 @@ use uom::si::mass::kilogram;
 mod shared;
 use shared::api::ApiDoc;
 use shared::astar;
+use shared::build;
 use shared::data;
+use shared::diff;
+use shared::export;
+use shared::pareto;
+use shared::patch;
 use shared::path;
 use shared::raw;
 use shared::search;
+use shared::spatial;
 use shared::tools;
+use shared::tour;
+use shared::validate;
 use utoipa::OpenApi;
 
 #[derive(Parser)]
@@ -27,34 +36,367 @@ struct Cli {
     command: Option<Commands>,
 }
 
+/// Systems and connections to keep out of the route
+#[derive(clap::Args)]
+struct AvoidArgs {
+    /// System to avoid, can be repeated or comma separated
+    #[clap(long = "avoid-system", value_delimiter = ',')]
+    systems: Vec<u32>,
+    /// Connection to avoid, can be repeated or comma separated
+    #[clap(long = "avoid-connection", value_delimiter = ',')]
+    connections: Vec<u32>,
+    /// Region to avoid entirely, can be repeated or comma separated
+    #[clap(long = "avoid-region", value_delimiter = ',')]
+    regions: Vec<u32>,
+}
+
+impl AvoidArgs {
+    fn to_avoid(&self, star_map: &data::StarMap) -> anyhow::Result<data::Avoid> {
+        let avoid = data::Avoid::from_system_ids(&self.systems, &self.connections)?;
+        Ok(avoid.with_regions(star_map, &self.regions))
+    }
+}
+
+/// Ship used to compute the fuel burnt by jumps
+#[derive(clap::Args)]
+struct ShipArgs {
+    /// Ship mass in kilograms, enables the fuel model
+    #[clap(long)]
+    ship_mass: Option<f64>,
+    /// Cargo mass in kilograms
+    #[clap(long)]
+    cargo_mass: Option<f64>,
+    #[clap(long, default_value = "eu90")]
+    fuel_type: data::FuelType,
+    /// Fuel efficiency between 0 and 1, overrides the fuel type
+    #[clap(long)]
+    fuel_efficiency: Option<f64>,
+}
+
+impl ShipArgs {
+    fn to_ship_fuel(&self) -> anyhow::Result<Option<data::ShipFuel>> {
+        self.ship_mass
+            .map(|mass| {
+                data::Ship {
+                    mass,
+                    cargo_mass: self.cargo_mass,
+                    fuel_type: Some(self.fuel_type),
+                    fuel_efficiency: self.fuel_efficiency,
+                }
+                .fuel_model()
+            })
+            .transpose()
+    }
+}
+
+/// Weights of the weighted optimization mode, and of the links of the fuel
+/// mode
+#[derive(clap::Args)]
+struct WeightArgs {
+    /// Cost per light-year jumped
+    #[clap(long, default_value = "1.0")]
+    jump_weight: f64,
+    /// Cost of taking a gate
+    #[clap(long, default_value = "1.0")]
+    gate_weight: f64,
+    /// Cost of taking a gate between constellations [default: gate weight]
+    #[clap(long)]
+    constellation_gate_weight: Option<f64>,
+    /// Cost of taking a gate between regions [default: gate weight]
+    #[clap(long)]
+    region_gate_weight: Option<f64>,
+    /// Cost of taking a one-way link [default: gate weight]
+    #[clap(long)]
+    one_way_weight: Option<f64>,
+    /// Cost of taking a smart gate
+    #[clap(long, default_value = "1.0")]
+    smart_gate_weight: f64,
+    /// Cost of taking a special link
+    #[clap(long, default_value = "1.0")]
+    special_weight: f64,
+    /// Cost added to every connection
+    #[clap(long, default_value = "0.0")]
+    hop_weight: f64,
+}
+
+impl WeightArgs {
+    fn to_weights(&self) -> anyhow::Result<data::CostWeights> {
+        let weights = data::CostWeights {
+            jump: self.jump_weight,
+            gate: self.gate_weight,
+            constellation_gate: self.constellation_gate_weight,
+            region_gate: self.region_gate_weight,
+            one_way: self.one_way_weight,
+            smart_gate: self.smart_gate_weight,
+            special: self.special_weight,
+            hop: self.hop_weight,
+        };
+        weights.validate()?;
+        Ok(weights)
+    }
+}
+
+/// Fuel tank limiting the jumps between refuels, not available with the
+/// bidirectional algorithm or alternatives
+#[derive(clap::Args)]
+struct TankArgs {
+    /// Tank capacity, in fuel units with a ship and light-years otherwise
+    #[clap(long)]
+    tank_capacity: Option<f64>,
+    /// Fuel at departure, defaults to a full tank
+    #[clap(long)]
+    tank_fuel: Option<f64>,
+    /// System where the tank can be refilled, can be repeated or comma separated
+    #[clap(long = "refuel-system", value_delimiter = ',')]
+    refuel_systems: Vec<u32>,
+}
+
+impl TankArgs {
+    fn to_tank(&self) -> anyhow::Result<Option<data::Tank>> {
+        self.tank_capacity
+            .map(|capacity| {
+                data::FuelTank {
+                    capacity,
+                    fuel: self.tank_fuel,
+                    refuel_systems: self.refuel_systems.clone(),
+                }
+                .tank()
+            })
+            .transpose()
+    }
+}
+
+/// When a search gives up
+#[derive(clap::Args)]
+struct LimitArgs {
+    /// Search timeout in milliseconds
+    #[clap(long, default_value = "60000")]
+    timeout_ms: u64,
+    /// Most systems the searches of the command may expand, all together
+    #[clap(long)]
+    max_visited: Option<u64>,
+}
+
+impl LimitArgs {
+    fn to_limits(&self) -> astar::SearchLimits {
+        astar::SearchLimits {
+            timeout: Some(Duration::from_millis(self.timeout_ms)),
+            max_visited: self.max_visited,
+            cancel: None,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Build the starmap from starmap.json
     Build {
+        /// Star map JSON file, optionally gzip compressed, or a
+        /// frontier-static-data extraction directory
         #[clap(short, long, default_value = "data/starmap.json")]
         source: String,
         #[clap(short, long, default_value = "data/starmap.bin")]
         output: String,
-        #[clap(short = 'x', long, default_value = "200.0")]
-        max_jump_distance: f64,
-        #[clap(short = 'i', long, default_value = "0.0")]
-        min_jump_distance: f64,
+        /// Number of threads, all the cores by default
+        #[clap(short, long)]
+        threads: Option<usize>,
+        /// Solar system names, a JSON list of solarSystemId and solarSystemName
+        #[clap(short, long)]
+        names: Option<String>,
+        /// Fail on any problem in the raw data, instead of skipping the bad
+        /// records
+        #[clap(long)]
+        strict: bool,
+        /// Write every problem found in the raw data to this file, as JSON
+        #[clap(long)]
+        report: Option<String>,
+    },
+    /// Apply a change set to a starmap, without building it again
+    Patch {
+        /// Changes to apply, as JSON
+        patch: String,
+        #[clap(short, long, default_value = "data/starmap.bin")]
+        source: String,
+        /// Where to write the patched starmap, the source by default
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    /// Show what changed between two starmaps
+    Diff {
+        old: String,
+        new: String,
+        /// Jump distance the jump neighbours are compared with
+        #[clap(short, long, default_value = "150")]
+        jump_distance: u16,
+        /// Print the changes as JSON
+        #[clap(long)]
+        json: bool,
+        /// Unit of the distances shown
+        #[clap(long, default_value = "ly")]
+        unit: data::DistanceUnit,
+    },
+    /// Export the starmap graph for other tools
+    Export {
+        #[clap(short, long, default_value = "graphml")]
+        format: export::ExportFormat,
+        /// Connection types to export, can be repeated or comma separated
+        #[clap(
+            short,
+            long,
+            value_delimiter = ',',
+            default_value = "gate,constellationgate,regiongate,oneway,special,smartgate"
+        )]
+        conn_type: Vec<data::ConnType>,
+        /// Longest jump exported, when jumps are exported
+        #[clap(short, long, default_value = "150")]
+        jump_distance: u16,
+        /// Output file, standard output by default. CSV needs one, and writes
+        /// the nodes and edges next to it.
+        #[clap(short, long)]
+        output: Option<String>,
+        /// Unit of the edge distances
+        #[clap(long, default_value = "ly")]
+        unit: data::DistanceUnit,
+        #[clap(short, long, default_value = "data/starmap.bin")]
+        source: String,
     },
     /// Find the shortest path between two stars
     Path {
-        start_id: u32,
-        end_id: u32,
+        /// Start system, by id or name
+        start_id: data::SystemRef,
+        /// End system, by id or name
+        end_id: data::SystemRef,
+        #[clap(short, long, default_value = "150")]
+        jump_distance: u16,
+        #[clap(short, long, default_value = "fuel")]
+        optimize: data::PathOptimize,
+        #[command(flatten)]
+        weights: WeightArgs,
+        #[clap(short, long, default_value = "astar")]
+        algorithm: data::PathAlgorithm,
+        #[command(flatten)]
+        avoid: AvoidArgs,
+        #[command(flatten)]
+        ship: ShipArgs,
+        #[command(flatten)]
+        limits: LimitArgs,
+        #[command(flatten)]
+        tank: TankArgs,
+        /// Number of alternative routes to show after the best one
+        #[clap(short = 'k', long, default_value = "0")]
+        alternatives: usize,
+        /// Unit of the distances shown
+        #[clap(long, default_value = "ly")]
+        unit: data::DistanceUnit,
+        #[clap(short, long, default_value = "data/starmap.bin")]
+        source: String,
+    },
+    /// Find a route through several stars, in the given order
+    Itinerary {
+        /// Systems, by id or name
+        #[clap(num_args = 2.., required = true)]
+        system_ids: Vec<data::SystemRef>,
+        #[clap(short, long, default_value = "150")]
+        jump_distance: u16,
+        #[clap(short, long, default_value = "fuel")]
+        optimize: data::PathOptimize,
+        #[command(flatten)]
+        weights: WeightArgs,
+        #[clap(short, long, default_value = "astar")]
+        algorithm: data::PathAlgorithm,
+        #[command(flatten)]
+        avoid: AvoidArgs,
+        #[command(flatten)]
+        ship: ShipArgs,
+        #[command(flatten)]
+        limits: LimitArgs,
+        #[command(flatten)]
+        tank: TankArgs,
+        /// Unit of the distances shown
+        #[clap(long, default_value = "ly")]
+        unit: data::DistanceUnit,
+        #[clap(short, long, default_value = "data/starmap.bin")]
+        source: String,
+    },
+    /// Find the cheapest order to visit several stars, starting with the first one
+    Tour {
+        /// Systems, by id or name
+        #[clap(num_args = 2.., required = true)]
+        system_ids: Vec<data::SystemRef>,
+        /// Come back to the first star at the end of the tour
+        #[clap(short, long)]
+        round_trip: bool,
         #[clap(short, long, default_value = "150")]
         jump_distance: u16,
         #[clap(short, long, default_value = "fuel")]
         optimize: data::PathOptimize,
+        #[command(flatten)]
+        weights: WeightArgs,
+        #[clap(short, long, default_value = "astar")]
+        algorithm: data::PathAlgorithm,
+        #[command(flatten)]
+        avoid: AvoidArgs,
+        #[command(flatten)]
+        ship: ShipArgs,
+        #[command(flatten)]
+        limits: LimitArgs,
+        #[command(flatten)]
+        tank: TankArgs,
+        /// Unit of the distances shown
+        #[clap(long, default_value = "ly")]
+        unit: data::DistanceUnit,
+        #[clap(short, long, default_value = "data/starmap.bin")]
+        source: String,
+    },
+    /// Find the routes trading off fuel, distance and hops between two stars
+    Pareto {
+        /// Start system, by id or name
+        start_id: data::SystemRef,
+        /// End system, by id or name
+        end_id: data::SystemRef,
+        #[clap(short, long, default_value = "150")]
+        jump_distance: u16,
+        #[command(flatten)]
+        avoid: AvoidArgs,
+        #[command(flatten)]
+        ship: ShipArgs,
+        #[command(flatten)]
+        limits: LimitArgs,
+        /// Unit of the distances shown
+        #[clap(long, default_value = "ly")]
+        unit: data::DistanceUnit,
         #[clap(short, long, default_value = "data/starmap.bin")]
         source: String,
     },
     /// Find the near stars to a given star
     Near {
-        star_id: u32,
+        /// Star, by id or name
+        star_id: data::SystemRef,
         max_distance: u16,
+        /// Unit of the distances shown
+        #[clap(long, default_value = "ly")]
+        unit: data::DistanceUnit,
+        #[clap(short, long, default_value = "data/starmap.bin")]
+        source: String,
+    },
+    /// List the stars of a constellation or a region
+    #[command(group(
+        clap::ArgGroup::new("area")
+            .required(true)
+            .multiple(true)
+            .args(["constellation", "region"])
+    ))]
+    Systems {
+        #[clap(short, long)]
+        constellation: Option<u32>,
+        #[clap(short, long)]
+        region: Option<u32>,
+        #[clap(short, long, default_value = "data/starmap.bin")]
+        source: String,
+    },
+    /// Show how a starmap was built, and check it
+    Info {
         #[clap(short, long, default_value = "data/starmap.bin")]
         source: String,
     },
@@ -66,27 +408,119 @@ enum Commands {
 }
 
 fn inject_smart_gate(
-    star_map: &mut HashMap<data::SolarSystemId, data::Star>,
+    smart_gates_map: &mut data::SmartGatesMap,
     from: u32,
     to: u32,
-    distance: u16,
+    distance: f64,
     id: u32,
 ) {
     let from_id = tools::system_id_to_u16(from).unwrap();
     let to_id = tools::system_id_to_u16(to).unwrap();
-    if let Some(from_system) = star_map.get_mut(&from_id) {
-        from_system.connections.insert(
-            0,
-            data::Connection {
-                conn_type: data::ConnType::SmartGate,
-                distance,
-                target: to_id,
-                id,
-            },
+    smart_gates_map
+        .entry(from_id)
+        .or_default()
+        .push(data::Connection {
+            conn_type: data::ConnType::SmartGate,
+            jump_type: 0,
+            distance,
+            target: to_id,
+            id,
+        });
+    info!(
+        "Injected smart gate {} between {} and {} ({:.2} ly)",
+        id, from, to, distance
+    );
+}
+
+/// A system id, followed by its name when the star map has one
+fn label(star_map: &data::StarMap, id: u32) -> String {
+    match tools::system_id_to_u16(id)
+        .ok()
+        .and_then(|id| star_map.name(id))
+    {
+        Some(name) => format!("{} ({})", id, name),
+        None => id.to_string(),
+    }
+}
+
+/// A system of a diff, followed by its name when known
+fn diff_label(system: &diff::DiffSystem) -> String {
+    match &system.name {
+        Some(name) => format!("{} ({})", system.id, name),
+        None => system.id.to_string(),
+    }
+}
+
+fn gate_label(gate: &diff::DiffGate) -> String {
+    let arrow = if gate.conn_type.two_way() {
+        "<->"
+    } else {
+        "->"
+    };
+    format!(
+        "{} {} {} {}",
+        gate.conn_type.name(),
+        diff_label(&gate.from),
+        arrow,
+        diff_label(&gate.to)
+    )
+}
+
+/// A distance, in the unit of a result
+fn length(distance: f64, unit: data::DistanceUnit) -> String {
+    format!("{:.2} {}", distance, unit.symbol())
+}
+
+fn print_diff(changes: &diff::StarMapDiff) {
+    for system in &changes.added_systems {
+        println!("+ system {}", diff_label(system));
+    }
+    for system in &changes.removed_systems {
+        println!("- system {}", diff_label(system));
+    }
+    for moved in &changes.moved_systems {
+        println!(
+            "~ system {} moved {}",
+            diff_label(&moved.system),
+            length(moved.distance, changes.unit)
         );
-        info!(
-            "Injected smart gate {} between {} and {} ({} ly)",
-            id, from, to, distance
+    }
+    for gate in &changes.added_gates {
+        println!("+ {}", gate_label(gate));
+    }
+    for gate in &changes.removed_gates {
+        println!("- {}", gate_label(gate));
+    }
+    for change in &changes.neighbourhoods {
+        println!("~ jumps from {}", diff_label(&change.system));
+        for system in &change.added {
+            println!("  + {}", diff_label(system));
+        }
+        for system in &change.removed {
+            println!("  - {}", diff_label(system));
+        }
+    }
+    println!(
+        "{} added, {} removed, {} moved systems, {} added, {} removed gates, {} systems with other jumps within {} ly",
+        changes.added_systems.len(),
+        changes.removed_systems.len(),
+        changes.moved_systems.len(),
+        changes.added_gates.len(),
+        changes.removed_gates.len(),
+        changes.neighbourhoods.len(),
+        changes.jump_distance
+    );
+}
+
+fn print_regions(regions: &[u32]) {
+    if !regions.is_empty() {
+        println!(
+            "Regions: {}",
+            regions
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ")
         );
     }
 }
@@ -101,168 +535,248 @@ fn main() -> anyhow::Result<()> {
         Some(Commands::Build {
             source,
             output,
-            max_jump_distance,
-            min_jump_distance,
+            threads,
+            names,
+            strict,
+            report,
         }) => {
             info!("Ensuring output directory exists");
             if let Some(parent) = std::path::Path::new(output).parent() {
                 std::fs::create_dir_all(parent)?;
             }
             info!("Loading raw data");
-            let raw_star_data = raw::RawStarMap::from_file(source);
-            let max_jump_dist: Length = Length::new::<light_year>(*max_jump_distance);
-            let min_jump_dist: Length = Length::new::<light_year>(*min_jump_distance);
-            info!("Building star map");
-            let mut star_map: HashMap<data::SolarSystemId, data::Star> = HashMap::new();
-            for (id_str, raw_star) in raw_star_data.solar_systems.iter() {
-                let id = tools::system_id_to_u16(id_str.parse()?)?;
-                let star = data::Star {
-                    id,
-                    x: raw_star.center[0],
-                    y: raw_star.center[1],
-                    z: raw_star.center[2],
-                    connections: Vec::new(),
-                };
-                star_map.insert(id, star);
+            let raw_star_data = raw::RawStarMap::from_path(source)?;
+            info!("Validating raw data");
+            let (raw_star_data, validation) = validate::validate(raw_star_data);
+            if let Some(report) = report {
+                std::fs::write(report, serde_json::to_string_pretty(&validation)?)?;
             }
-
-            info!("Building connections from npc gates");
-            let mut conn_count = 0;
-            for raw_jump in raw_star_data.jumps.iter() {
-                // rust only lets us borrow one mutable star at a time, so we can't add
-                // from->to and to->from gates in the same block
-                for (fid, tid) in [
-                    (raw_jump.from_system_id, raw_jump.to_system_id),
-                    (raw_jump.to_system_id, raw_jump.from_system_id),
-                ] {
-                    let fid = tools::system_id_to_u16(fid)?;
-                    let tid = tools::system_id_to_u16(tid)?;
-                    let to_star = star_map.get(&tid).unwrap().clone();
-                    let from_star = star_map.get_mut(&fid).unwrap();
-                    let distance: Length = from_star.distance(&to_star);
-                    let conn_type = match raw_jump.jump_type {
-                        0 => data::ConnType::Gate,
-                        1 => data::ConnType::Gate,
-                        _ => {
-                            info!(
-                                "{} -> {} is an unknown jump type ({})",
-                                fid, tid, raw_jump.jump_type
-                            );
-                            continue;
-                        }
-                    };
-                    from_star.connections.push(data::Connection {
-                        id: conn_count,
-                        conn_type,
-                        distance: distance.get::<light_year>() as u16,
-                        target: tid,
-                    });
-                    conn_count += 1;
+            let errors = validation.errors().count();
+            if *strict && errors > 0 {
+                for problem in validation.errors() {
+                    warn!("{}", problem);
                 }
+                anyhow::bail!("{} problems in the raw data, nothing was built", errors);
             }
-
-            // info!("Building connections from smart gates");
-            // let smart_gates: Vec<raw::RawSmartGate> =
-            //     serde_json::from_str(&std::fs::read_to_string("data/smartgates.json")?)?;
-            // for gate in smart_gates.iter() {
-            //     if !star_map.contains_key(&gate.from) {
-            //         warn!("Smart gate has unknown source {}", gate.from);
-            //         continue;
-            //     }
-            //     if !star_map.contains_key(&gate.to) {
-            //         warn!("Smart gate has unknown target {}", gate.to);
-            //         continue;
-            //     }
-            //     let to_star = star_map.get(&gate.to).unwrap().clone();
-            //     let from_star = star_map.get_mut(&gate.from).unwrap();
-            //     let distance: Length = from_star.distance(&to_star);
-            //     from_star.connections.push(data::Connection {
-            //         id: conn_count,
-            //         conn_type: data::ConnType::SmartGate,
-            //         distance,
-            //         target: gate.to,
-            //     });
-            //     conn_count += 1;
-            // }
-
-            info!("Building connections from jumps");
-            let cloned_star_map = star_map.clone();
-            for star in star_map.values_mut().progress() {
-                for other_star in cloned_star_map.values() {
-                    if star.id == other_star.id {
-                        continue;
-                    }
-                    let distance: Length = star.distance(&other_star);
-                    if distance < max_jump_dist && distance > min_jump_dist {
-                        star.connections.push(data::Connection {
-                            id: conn_count,
-                            conn_type: data::ConnType::Jump,
-                            distance: distance.get::<light_year>() as u16,
-                            target: other_star.id,
-                        });
-                        conn_count += 1;
-                    }
-                }
+            for (kind, count) in validation.summary() {
+                warn!("Skipped {} records: {}", count, kind);
             }
-            info!("Found {} connections", conn_count);
-
-            info!("Sorting connections");
-            // sort gates first, and then jumps by distance - then when we
-            // reach a jump that is too long we can stop searching
-            for star in star_map.values_mut().progress() {
-                star.connections.sort_unstable();
+            for (kind, count) in validation.warnings() {
+                warn!("Kept {} records: {}", count, kind);
             }
+            info!(
+                "Building from {} systems and {} jumps",
+                validation.systems, validation.jumps
+            );
+            let raw_names = match names {
+                Some(names) => raw::RawStar::from_file(names)?,
+                None => Vec::new(),
+            };
+            let build_source =
+                data::BuildSource::from_files(&raw::RawStarMap::source_files(source)?)?;
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads.unwrap_or(0))
+                .build()?;
+            info!(
+                "Building star map on {} threads",
+                pool.current_num_threads()
+            );
+            let star_map =
+                pool.install(|| build::build_star_map(&raw_star_data, &raw_names, &build_source))?;
 
             info!("Saving star map");
             data::save_star_map(&star_map, output)?;
             info!("Complete");
         }
+        Some(Commands::Patch {
+            patch,
+            source,
+            output,
+        }) => {
+            info!("Loading star map");
+            let star_map = data::get_star_map(source)?;
+            let changes = patch::StarMapPatch::from_file(patch)?;
+            info!("Patching star map");
+            let patched = patch::patch_star_map(&star_map, &changes)?;
+            data::save_star_map(&patched, output.as_deref().unwrap_or(source))?;
+            info!("Complete");
+        }
+        Some(Commands::Diff {
+            old,
+            new,
+            jump_distance,
+            json,
+            unit,
+        }) => {
+            let old = data::get_star_map(old)?;
+            let new = data::get_star_map(new)?;
+            info!("Comparing star maps");
+            let changes = diff::diff_star_maps(&old, &new, *jump_distance, *unit);
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&changes)?);
+            } else {
+                print_diff(&changes);
+            }
+        }
+        Some(Commands::Export {
+            format,
+            conn_type,
+            jump_distance,
+            output,
+            unit,
+            source,
+        }) => {
+            let star_map = data::get_star_map(source)?;
+            info!("Building the graph");
+            let graph = export::graph(
+                &star_map,
+                &export::ExportOptions {
+                    conn_types: conn_type.clone(),
+                    jump_distance: *jump_distance,
+                    unit: *unit,
+                },
+            );
+            info!(
+                "Exporting {} nodes and {} edges",
+                graph.nodes.len(),
+                graph.edges.len()
+            );
+            match (format, output) {
+                (export::ExportFormat::Csv, None) => {
+                    anyhow::bail!("CSV exports need an output file")
+                }
+                (export::ExportFormat::Csv, Some(output)) => {
+                    let output = std::path::Path::new(output);
+                    let nodes = output.with_extension("nodes.csv");
+                    let edges = output.with_extension("edges.csv");
+                    let mut nodes_out = BufWriter::new(File::create(&nodes)?);
+                    let mut edges_out = BufWriter::new(File::create(&edges)?);
+                    export::write_csv(&graph, &mut nodes_out, &mut edges_out)?;
+                    nodes_out.flush()?;
+                    edges_out.flush()?;
+                    info!("Wrote {} and {}", nodes.display(), edges.display());
+                }
+                (format, output) => {
+                    let mut out: Box<dyn Write> = match output {
+                        Some(output) => Box::new(BufWriter::new(File::create(output)?)),
+                        None => Box::new(std::io::stdout().lock()),
+                    };
+                    match format {
+                        export::ExportFormat::Graphml => export::write_graphml(&graph, &mut out)?,
+                        export::ExportFormat::Dot => export::write_dot(&graph, &mut out)?,
+                        export::ExportFormat::Json => export::write_json(&graph, &mut out)?,
+                        export::ExportFormat::Csv => unreachable!("Written above"),
+                    }
+                    out.flush()?;
+                }
+            }
+        }
         Some(Commands::Path {
             start_id,
             end_id,
             jump_distance,
             optimize,
+            weights,
+            algorithm,
+            avoid,
+            ship,
+            limits,
+            tank,
+            alternatives,
+            unit,
             source,
         }) => {
             info!("Loading star map");
             let now = Instant::now();
-            let mut star_map = data::get_star_map(source)?;
+            let star_map = data::get_star_map(source)?;
             info!("Loaded star map in {:.3}", now.elapsed().as_secs_f64());
 
             // Inject a smart gate between
-            inject_smart_gate(&mut star_map, 30013484, 30013460, 319, u32::MAX);
-            inject_smart_gate(&mut star_map, 30013460, 30013933, 440, u32::MAX - 1);
-            inject_smart_gate(&mut star_map, 30013933, 30022226, 229, u32::MAX - 2);
-            inject_smart_gate(&mut star_map, 30013460, 30013484, 319, u32::MAX - 3);
-            inject_smart_gate(&mut star_map, 30013933, 30013460, 440, u32::MAX - 4);
-            inject_smart_gate(&mut star_map, 30022226, 30013933, 229, u32::MAX - 5);
-
-            let start = star_map
-                .get(&tools::system_id_to_u16(*start_id).unwrap())
-                .unwrap();
-            let end = star_map
-                .get(&tools::system_id_to_u16(*end_id).unwrap())
-                .unwrap();
+            let mut smart_gates_map: data::SmartGatesMap = HashMap::new();
+            inject_smart_gate(&mut smart_gates_map, 30013484, 30013460, 319.0, u32::MAX);
+            inject_smart_gate(
+                &mut smart_gates_map,
+                30013460,
+                30013933,
+                440.0,
+                u32::MAX - 1,
+            );
+            inject_smart_gate(
+                &mut smart_gates_map,
+                30013933,
+                30022226,
+                229.0,
+                u32::MAX - 2,
+            );
+            inject_smart_gate(
+                &mut smart_gates_map,
+                30013460,
+                30013484,
+                319.0,
+                u32::MAX - 3,
+            );
+            inject_smart_gate(
+                &mut smart_gates_map,
+                30013933,
+                30013460,
+                440.0,
+                u32::MAX - 4,
+            );
+            inject_smart_gate(
+                &mut smart_gates_map,
+                30022226,
+                30013933,
+                229.0,
+                u32::MAX - 5,
+            );
+
+            let start = star_map.find(start_id)?;
+            let end = star_map.find(end_id)?;
 
             info!("Finding path");
             let now = Instant::now();
-            let smart_gates_map: data::SmartGatesMap = HashMap::new();
-            let path = path::calc_path(
+            let options = data::PathOptions {
+                jump_distance: *jump_distance,
+                optimize: *optimize,
+                weights: weights.to_weights()?,
+                algorithm: *algorithm,
+                avoid: avoid.to_avoid(&star_map)?,
+                ship: ship.to_ship_fuel()?,
+                tank: tank.to_tank()?,
+                limits: limits.to_limits(),
+                unit: *unit,
+            };
+            options.validate(*alternatives)?;
+            let path = path::calc_alternatives(
                 &star_map,
                 &smart_gates_map,
                 start,
                 end,
-                *jump_distance,
-                *optimize,
-                Some(60),
+                &options,
+                *alternatives,
             );
             let mut last_id = tools::u16_to_system_id(start.id);
             let path_len = path.path.len();
             for conn in path.path {
-                println!(
-                    "{} -> {} ({:?}, {} ly)",
-                    last_id, conn.target, conn.conn_type, conn.distance
-                );
+                match conn.fuel {
+                    Some(fuel) => println!(
+                        "{} -> {} ({:?} #{}, {}, {:.2} fuel)",
+                        label(&star_map, last_id),
+                        label(&star_map, conn.target),
+                        conn.conn_type,
+                        conn.id,
+                        length(conn.distance, *unit),
+                        fuel
+                    ),
+                    None => println!(
+                        "{} -> {} ({:?} #{}, {})",
+                        label(&star_map, last_id),
+                        label(&star_map, conn.target),
+                        conn.conn_type,
+                        conn.id,
+                        length(conn.distance, *unit)
+                    ),
+                }
                 last_id = conn.target;
             }
             println!(
@@ -281,10 +795,242 @@ fn main() -> anyhow::Result<()> {
                 path.stats.loop_spend,
                 path.stats.total_time,
             );
+            print_regions(&path.regions);
+            if let Some(fuel) = path.fuel {
+                println!("Fuel: {:.2}", fuel);
+            }
+            if let Some(fuel_left) = path.fuel_left {
+                println!("Refuel in: {:?}, Fuel left: {:.2}", path.refuels, fuel_left);
+            }
+            for (i, alternative) in path.alternatives.iter().enumerate() {
+                println!(
+                    "Alternative {}: {} nodes, Cost: {}",
+                    i + 1,
+                    alternative.path.len(),
+                    alternative.cost
+                );
+                let mut last_id = tools::u16_to_system_id(start.id);
+                for conn in &alternative.path {
+                    println!(
+                        "  {} -> {} ({:?}, {})",
+                        label(&star_map, last_id),
+                        label(&star_map, conn.target),
+                        conn.conn_type,
+                        length(conn.distance, *unit)
+                    );
+                    last_id = conn.target;
+                }
+            }
+        }
+        Some(Commands::Itinerary {
+            system_ids,
+            jump_distance,
+            optimize,
+            weights,
+            algorithm,
+            avoid,
+            ship,
+            limits,
+            tank,
+            unit,
+            source,
+        }) => {
+            info!("Loading star map");
+            let now = Instant::now();
+            let star_map = data::get_star_map(source)?;
+            info!("Loaded star map in {:.3}", now.elapsed().as_secs_f64());
+
+            let stops = system_ids
+                .iter()
+                .map(|system| star_map.find(system))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            info!("Finding itinerary");
+            let now = Instant::now();
+            let smart_gates_map: data::SmartGatesMap = HashMap::new();
+            let options = data::PathOptions {
+                jump_distance: *jump_distance,
+                optimize: *optimize,
+                weights: weights.to_weights()?,
+                algorithm: *algorithm,
+                avoid: avoid.to_avoid(&star_map)?,
+                ship: ship.to_ship_fuel()?,
+                tank: tank.to_tank()?,
+                limits: limits.to_limits(),
+                unit: *unit,
+            };
+            options.validate(0)?;
+            let itinerary = path::calc_itinerary(&star_map, &smart_gates_map, &stops, &options);
+            for leg in &itinerary.legs {
+                println!(
+                    "Leg {} -> {}: {:?} {} nodes, Cost: {}, Visited: {} nodes",
+                    label(&star_map, leg.from),
+                    label(&star_map, leg.to),
+                    leg.status,
+                    leg.length,
+                    leg.stats.cost,
+                    leg.stats.visited
+                );
+                let mut last_id = leg.from;
+                for conn in &itinerary.path[leg.start..leg.start + leg.length] {
+                    println!(
+                        "  {} -> {} ({:?}, {})",
+                        label(&star_map, last_id),
+                        label(&star_map, conn.target),
+                        conn.conn_type,
+                        length(conn.distance, *unit)
+                    );
+                    last_id = conn.target;
+                }
+            }
+            println!(
+                "Itinerary through {} stars: {:?} {} nodes, Cost: {} in {:.3}s",
+                stops.len(),
+                itinerary.status,
+                itinerary.path.len(),
+                itinerary.cost,
+                now.elapsed().as_secs_f64()
+            );
+            print_regions(&itinerary.regions);
+        }
+        Some(Commands::Tour {
+            system_ids,
+            round_trip,
+            jump_distance,
+            optimize,
+            weights,
+            algorithm,
+            avoid,
+            ship,
+            limits,
+            tank,
+            unit,
+            source,
+        }) => {
+            info!("Loading star map");
+            let now = Instant::now();
+            let star_map = data::get_star_map(source)?;
+            info!("Loaded star map in {:.3}", now.elapsed().as_secs_f64());
+
+            let stops = system_ids
+                .iter()
+                .map(|system| star_map.find(system))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            info!("Finding tour");
+            let now = Instant::now();
+            let smart_gates_map: data::SmartGatesMap = HashMap::new();
+            let options = data::PathOptions {
+                jump_distance: *jump_distance,
+                optimize: *optimize,
+                weights: weights.to_weights()?,
+                algorithm: *algorithm,
+                avoid: avoid.to_avoid(&star_map)?,
+                ship: ship.to_ship_fuel()?,
+                tank: tank.to_tank()?,
+                limits: limits.to_limits(),
+                unit: *unit,
+            };
+            options.validate(0)?;
+            let tour = tour::calc_tour(&star_map, &smart_gates_map, &stops, &options, *round_trip);
+            for leg in &tour.legs {
+                println!(
+                    "Leg {} -> {}: {:?} {} nodes, Cost: {}",
+                    label(&star_map, leg.from),
+                    label(&star_map, leg.to),
+                    leg.status,
+                    leg.length,
+                    leg.stats.cost
+                );
+            }
+            println!(
+                "Tour through {} stars ({}): {:?} {} nodes, Cost: {} in {:.3}s",
+                stops.len(),
+                if tour.exact { "exact" } else { "heuristic" },
+                tour.status,
+                tour.path.len(),
+                tour.cost,
+                now.elapsed().as_secs_f64()
+            );
+            println!(
+                "Order: {}",
+                tour.order
+                    .iter()
+                    .map(|id| label(&star_map, *id))
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            );
+            if let Some(unreachable) = tour.unreachable {
+                println!("No route to or from {}", label(&star_map, unreachable));
+            }
+            print_regions(&tour.regions);
+        }
+        Some(Commands::Pareto {
+            start_id,
+            end_id,
+            jump_distance,
+            avoid,
+            ship,
+            limits,
+            unit,
+            source,
+        }) => {
+            info!("Loading star map");
+            let now = Instant::now();
+            let star_map = data::get_star_map(source)?;
+            info!("Loaded star map in {:.3}", now.elapsed().as_secs_f64());
+
+            let start = star_map.find(start_id)?;
+            let end = star_map.find(end_id)?;
+
+            info!("Finding routes");
+            let now = Instant::now();
+            let smart_gates_map: data::SmartGatesMap = HashMap::new();
+            let options = data::PathOptions {
+                jump_distance: *jump_distance,
+                optimize: data::PathOptimize::Fuel,
+                weights: data::CostWeights::default(),
+                algorithm: data::PathAlgorithm::default(),
+                avoid: avoid.to_avoid(&star_map)?,
+                ship: ship.to_ship_fuel()?,
+                tank: None,
+                limits: limits.to_limits(),
+                unit: *unit,
+            };
+            let result = pareto::calc_pareto(&star_map, &smart_gates_map, start, end, &options);
+            for route in &result.routes {
+                println!(
+                    "Fuel: {}, Distance: {}, Hops: {}",
+                    route.fuel,
+                    length(route.distance, *unit),
+                    route.hops
+                );
+                let mut last_id = tools::u16_to_system_id(start.id);
+                for conn in &route.path {
+                    println!(
+                        "  {} -> {} ({:?}, {})",
+                        label(&star_map, last_id),
+                        label(&star_map, conn.target),
+                        conn.conn_type,
+                        length(conn.distance, *unit)
+                    );
+                    last_id = conn.target;
+                }
+            }
+            println!(
+                "Pareto front from {} to {}: {:?} {} routes, Visited: {} nodes in {:.3}s",
+                start_id,
+                end_id,
+                result.status,
+                result.routes.len(),
+                result.visited,
+                now.elapsed().as_secs_f64()
+            );
         }
         Some(Commands::Near {
             star_id,
             max_distance,
+            unit,
             source,
         }) => {
             info!("Loading star map");
@@ -292,12 +1038,25 @@ fn main() -> anyhow::Result<()> {
             let star_map = data::get_star_map(source)?;
             info!("Loaded star map in {:.3}", now.elapsed().as_secs_f64());
 
-            let star = star_map
-                .get(&tools::system_id_to_u16(*star_id).unwrap())
-                .unwrap();
-            let result = search::near(&star_map, star, *max_distance);
+            let star = star_map.find(star_id)?;
+            let result = search::near(&star_map, star, *max_distance, *unit);
             println!("{}", serde_json::to_string_pretty(&result)?);
         }
+        Some(Commands::Systems {
+            constellation,
+            region,
+            source,
+        }) => {
+            let star_map = data::get_star_map(source)?;
+            let result = search::systems(&star_map, *constellation, *region);
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        }
+        Some(Commands::Info { source }) => {
+            let info = data::get_star_map_info(source)?;
+            println!("{}", serde_json::to_string_pretty(&info)?);
+            data::get_star_map(source)?;
+            info!("Star map is valid");
+        }
         Some(Commands::ApiDoc { dest }) => {
             let api_doc = ApiDoc::openapi();
             let json = serde_json::to_string_pretty(&api_doc)?;
@@ -1,6 +1,7 @@
 use lambda_runtime::{tracing, Error, LambdaEvent};
-use std::collections::HashMap;
 use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::time::Duration;
 
 use crate::shared::astar;
 use crate::shared::data;
@@ -11,16 +12,38 @@ use crate::shared::tools;
 pub struct SmartGateLink {
     pub from: u32,
     pub to: u32,
-    pub distance: u16,
+    /// Light-years
+    pub distance: f64,
     pub id: u32,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct EventPayload {
-    pub from: u32,
-    pub to: u32,
+    /// Start system, by id or name
+    pub from: data::SystemRef,
+    /// End system, by id or name
+    pub to: data::SystemRef,
     pub jump_distance: u16,
     pub optimize: Option<data::PathOptimize>,
+    pub weights: Option<data::CostWeights>,
+    pub algorithm: Option<data::PathAlgorithm>,
+    /// Systems the route must not go through
+    pub avoid_systems: Option<Vec<u32>>,
+    /// Connections the route must not use
+    pub avoid_connections: Option<Vec<u32>>,
+    /// Regions the route must not go through
+    pub avoid_regions: Option<Vec<u32>>,
+    pub ship: Option<data::Ship>,
+    pub tank: Option<data::FuelTank>,
+    /// Number of alternative routes to return along with the best one
+    pub alternatives: Option<usize>,
+    /// Search timeout in milliseconds, 25000 at most
+    pub timeout_ms: Option<u64>,
+    /// Most systems the searches of the request may expand, all together,
+    /// before giving up
+    pub max_visited: Option<u64>,
+    /// Unit of the distances in the result, light-years by default
+    pub unit: Option<data::DistanceUnit>,
     pub smart_gates: Vec<SmartGateLink>,
 }
 
@@ -43,14 +66,12 @@ pub(crate) async fn function_handler(
     for smart_gate in &payload.smart_gates {
         let from_id = tools::system_id_to_u16(smart_gate.from).unwrap();
         let to_id = tools::system_id_to_u16(smart_gate.to).unwrap();
-        if !smart_gates_map.contains_key(&from_id) {
-            smart_gates_map.insert(from_id, Vec::new());
-        }
         smart_gates_map
-            .get_mut(&from_id)
-            .unwrap()
+            .entry(from_id)
+            .or_default()
             .push(data::Connection {
                 conn_type: data::ConnType::SmartGate,
+                jump_type: 0,
                 distance: smart_gate.distance,
                 target: to_id,
                 id: smart_gate.id,
@@ -64,21 +85,47 @@ pub(crate) async fn function_handler(
         elapsed
     );
 
-    let start = star_map
-        .get(&tools::system_id_to_u16(payload.from).unwrap())
-        .unwrap();
-    let end = star_map
-        .get(&tools::system_id_to_u16(payload.to).unwrap())
-        .unwrap();
+    let start = star_map.find(&payload.from)?;
+    let end = star_map.find(&payload.to)?;
 
-    let path = path::calc_path(
-        &star_map,
+    let options = data::PathOptions {
+        jump_distance: payload.jump_distance,
+        optimize: payload.optimize.unwrap(),
+        weights: {
+            let weights = payload.weights.unwrap_or_default();
+            weights.validate()?;
+            weights
+        },
+        algorithm: payload.algorithm.unwrap_or_default(),
+        avoid: data::Avoid::from_system_ids(
+            payload.avoid_systems.as_deref().unwrap_or_default(),
+            payload.avoid_connections.as_deref().unwrap_or_default(),
+        )?
+        .with_regions(
+            star_map,
+            payload.avoid_regions.as_deref().unwrap_or_default(),
+        ),
+        ship: payload.ship.as_ref().map(|s| s.fuel_model()).transpose()?,
+        tank: payload.tank.as_ref().map(|t| t.tank()).transpose()?,
+        limits: astar::SearchLimits {
+            timeout: Some(Duration::from_millis(
+                payload.timeout_ms.unwrap_or(25_000).min(25_000),
+            )),
+            max_visited: payload.max_visited,
+            cancel: None,
+            ..Default::default()
+        },
+        unit: payload.unit.unwrap_or_default(),
+    };
+    let alternatives = payload.alternatives.unwrap_or(0);
+    options.validate(alternatives)?;
+    let path = path::calc_alternatives(
+        star_map,
         &smart_gates_map,
         start,
         end,
-        payload.jump_distance,
-        payload.optimize.unwrap(),
-        Some(25),
+        &options,
+        alternatives,
     );
     tracing::info!("Path: {:?}", path);
     Ok(path)
@@ -106,10 +153,21 @@ mod tests {
 
         let event = LambdaEvent::new(
             EventPayload {
-                from: 30001573,
-                to: 30013956,
+                from: data::SystemRef::Id(30001573),
+                to: data::SystemRef::Id(30013956),
                 jump_distance: 150,
                 optimize: Some(data::PathOptimize::Fuel),
+                weights: None,
+                algorithm: None,
+                avoid_systems: None,
+                avoid_connections: None,
+                avoid_regions: None,
+                ship: None,
+                tank: None,
+                timeout_ms: None,
+                max_visited: None,
+                alternatives: None,
+                unit: None,
                 smart_gates: vec![],
             },
             Context::default(),
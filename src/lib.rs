@@ -1,6 +1,15 @@
 mod shared;
 
+pub use shared::astar;
+pub use shared::build;
 pub use shared::data;
+pub use shared::diff;
+pub use shared::export;
+pub use shared::pareto;
+pub use shared::patch;
 pub use shared::path;
 pub use shared::search;
+pub use shared::spatial;
 pub use shared::tools;
+pub use shared::tour;
+pub use shared::validate;
@@ -29,7 +29,9 @@ use shared::path;
 use shared::search;
 use shared::tools;
 
-use crate::shared::api::{calc_near, calc_path};
+use crate::shared::api::{
+    calc_itinerary, calc_near, calc_pareto, calc_path, calc_systems, calc_tour,
+};
 
 #[rocket::get("/")]
 fn root() -> &'static str {
@@ -59,8 +61,18 @@ fn rocket() -> _ {
     let semaphore = Arc::new(Semaphore::new(max_concurrent_requests)); // Limit to max concurrent requests on path finder
 
     rocket::build()
-        .manage(map)
+        .manage(Arc::new(map))
         .manage(semaphore)
-        .mount("/api", routes![calc_path, calc_near])
+        .mount(
+            "/api",
+            routes![
+                calc_path,
+                calc_itinerary,
+                calc_tour,
+                calc_pareto,
+                calc_near,
+                calc_systems
+            ],
+        )
         .mount("/", routes![root])
 }
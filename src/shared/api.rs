@@ -1,17 +1,20 @@
 use log::{info, warn};
 use std::collections::HashMap;
+use std::fmt::Debug;
 use std::io::Cursor;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use rocket::fs::NamedFile;
 use rocket::http::ContentType;
 use rocket::http::Status;
 use rocket::request::Request;
+use rocket::response::stream::ByteStream;
 use rocket::response::{self, Responder, Response};
 use rocket::serde::json::Json;
 use rocket::State;
-use tokio::sync::Semaphore;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 use serde::{Deserialize, Serialize};
 use uom::si::f64::*;
@@ -19,10 +22,13 @@ use uom::si::length::light_year;
 use uom::si::mass::kilogram;
 use utoipa::{OpenApi, ToSchema};
 
+use super::astar;
 use super::data;
+use super::pareto;
 use super::path;
 use super::search;
 use super::tools;
+use super::tour;
 
 // ====================================================================
 // common
@@ -31,16 +37,40 @@ use super::tools;
 pub struct SmartGateLink {
     pub from: u32,
     pub to: u32,
-    pub distance: u16,
+    /// Light-years
+    pub distance: f64,
     pub id: u32,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct EventPayload {
-    pub from: u32,
-    pub to: u32,
+    pub from: data::SystemRef,
+    pub to: data::SystemRef,
     pub jump_distance: u16,
     pub optimize: Option<data::PathOptimize>,
+    /// Weights of the weighted optimization mode
+    pub weights: Option<data::CostWeights>,
+    pub algorithm: Option<data::PathAlgorithm>,
+    /// Systems the route must not go through
+    pub avoid_systems: Option<Vec<u32>>,
+    /// Connections the route must not use
+    pub avoid_connections: Option<Vec<u32>>,
+    /// Regions the route must not go through
+    pub avoid_regions: Option<Vec<u32>>,
+    /// Ship used to compute the fuel burnt by jumps
+    pub ship: Option<data::Ship>,
+    /// Limit jumps to what a fuel tank can hold, refuelling in some systems.
+    /// Not available with the bidirectional algorithm or alternatives.
+    pub tank: Option<data::FuelTank>,
+    /// Number of alternative routes to return along with the best one
+    pub alternatives: Option<usize>,
+    /// Search timeout in milliseconds, 25000 at most
+    pub timeout_ms: Option<u64>,
+    /// Most systems the searches of the request may expand, all together,
+    /// before giving up
+    pub max_visited: Option<u64>,
+    /// Unit of the distances in the result, light-years by default
+    pub unit: Option<data::DistanceUnit>,
     pub smart_gates: Vec<SmartGateLink>,
 }
 
@@ -64,13 +94,204 @@ impl From<anyhow::Error> for CustomError {
     }
 }
 
+fn smart_gates_map(smart_gates: &[SmartGateLink]) -> data::SmartGatesMap {
+    let mut smart_gates_map: data::SmartGatesMap = HashMap::new();
+    for smart_gate in smart_gates {
+        let from_id = tools::system_id_to_u16(smart_gate.from).unwrap();
+        let to_id = tools::system_id_to_u16(smart_gate.to).unwrap();
+        smart_gates_map
+            .entry(from_id)
+            .or_default()
+            .push(data::Connection {
+                conn_type: data::ConnType::SmartGate,
+                jump_type: 0,
+                distance: smart_gate.distance,
+                target: to_id,
+                id: smart_gate.id,
+            });
+    }
+    smart_gates_map
+}
+
+fn ship_fuel(ship: &Option<data::Ship>) -> Result<Option<data::ShipFuel>, CustomError> {
+    ship.as_ref()
+        .map(|ship| ship.fuel_model())
+        .transpose()
+        .map_err(|err| CustomError(Status::BadRequest, err.to_string()))
+}
+
+fn fuel_tank(tank: &Option<data::FuelTank>) -> Result<Option<data::Tank>, CustomError> {
+    tank.as_ref()
+        .map(|tank| tank.tank())
+        .transpose()
+        .map_err(|err| CustomError(Status::BadRequest, err.to_string()))
+}
+
+fn avoid(
+    star_map: &data::StarMap,
+    systems: &Option<Vec<u32>>,
+    connections: &Option<Vec<u32>>,
+    regions: &Option<Vec<u32>>,
+) -> Result<data::Avoid, CustomError> {
+    let avoid = data::Avoid::from_system_ids(
+        systems.as_deref().unwrap_or_default(),
+        connections.as_deref().unwrap_or_default(),
+    )
+    .map_err(|err| CustomError(Status::BadRequest, err.to_string()))?;
+    Ok(avoid.with_regions(star_map, regions.as_deref().unwrap_or_default()))
+}
+
+fn cost_weights(weights: &Option<data::CostWeights>) -> Result<data::CostWeights, CustomError> {
+    let weights = weights.unwrap_or_default();
+    weights
+        .validate()
+        .map_err(|err| CustomError(Status::BadRequest, err.to_string()))?;
+    Ok(weights)
+}
+
+/// Longest a search may run on the server
+const MAX_TIMEOUT_MS: u64 = 25_000;
+
+fn search_limits(
+    timeout_ms: Option<u64>,
+    max_visited: Option<u64>,
+    cancel: &astar::CancelToken,
+) -> astar::SearchLimits {
+    let timeout_ms = timeout_ms.unwrap_or(MAX_TIMEOUT_MS).min(MAX_TIMEOUT_MS);
+    astar::SearchLimits {
+        timeout: Some(Duration::from_millis(timeout_ms)),
+        max_visited,
+        cancel: Some(cancel.clone()),
+        ..Default::default()
+    }
+}
+
+/// Cancels a search when dropped before it completes
+struct CancelOnDrop(Option<astar::CancelToken>);
+
+impl CancelOnDrop {
+    fn disarm(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(cancel) = self.0.take() {
+            warn!("Client went away, cancelling the search");
+            cancel.cancel();
+        }
+    }
+}
+
+/// How often a space is sent while a search runs
+const HEARTBEAT: Duration = Duration::from_millis(500);
+
+/// Sent instead of the result when a search fails once its response has
+/// started, as the HTTP status cannot change anymore
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchError {
+    /// Always `error`
+    pub status: String,
+    pub message: String,
+}
+
+fn search_error(message: String) -> Vec<u8> {
+    warn!("Search failed: {}", message);
+    let error = SearchError {
+        status: "error".to_string(),
+        message,
+    };
+    serde_json::to_vec(&error).expect("Strings always serialize")
+}
+
+/// Run a search on the blocking thread pool and stream its JSON result.
+///
+/// Rocket never drops a handler when the client goes away, only the response
+/// body once writing it fails. So the body sends a space every `HEARTBEAT`
+/// while the search runs, which is valid leading whitespace for the JSON
+/// that follows, and the search is cancelled as soon as the body is dropped.
+/// The concurrency permit is held until the search completes. A failed
+/// search ends the body with a `SearchError`.
+fn search_response<T, F>(
+    permit: OwnedSemaphorePermit,
+    cancel: astar::CancelToken,
+    search: F,
+) -> (ContentType, ByteStream![Vec<u8>])
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Serialize + Debug + Send + 'static,
+{
+    let mut guard = CancelOnDrop(Some(cancel));
+    let mut task = tokio::task::spawn_blocking(search);
+    let body = ByteStream! {
+        let _permit = permit;
+        let mut heartbeat = tokio::time::interval(HEARTBEAT);
+        loop {
+            let done = tokio::select! {
+                result = &mut task => Some(result),
+                _ = heartbeat.tick() => None,
+            };
+            let Some(result) = done else {
+                yield vec![b' '];
+                continue;
+            };
+            guard.disarm();
+            yield match result.map(|result| {
+                info!("Result: {:?}", result);
+                serde_json::to_vec(&result)
+            }) {
+                Ok(Ok(body)) => body,
+                Ok(Err(err)) => search_error(err.to_string()),
+                Err(err) => search_error(err.to_string()),
+            };
+            break;
+        }
+    };
+    (ContentType::JSON, body)
+}
+
+fn get_star<'a>(
+    star_map: &'a data::StarMap,
+    system: &data::SystemRef,
+) -> Result<&'a data::Star, CustomError> {
+    star_map
+        .find(system)
+        .map_err(|err| CustomError(Status::NotFound, err.to_string()))
+}
+
 // POST /api/path
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct PathPayload {
-    pub from: u32,
-    pub to: u32,
+    /// Start system, by id or name
+    pub from: data::SystemRef,
+    /// End system, by id or name
+    pub to: data::SystemRef,
     pub jump_distance: u16,
     pub optimize: Option<data::PathOptimize>,
+    /// Weights of the weighted optimization mode
+    pub weights: Option<data::CostWeights>,
+    pub algorithm: Option<data::PathAlgorithm>,
+    /// Systems the route must not go through
+    pub avoid_systems: Option<Vec<u32>>,
+    /// Connections the route must not use
+    pub avoid_connections: Option<Vec<u32>>,
+    /// Regions the route must not go through
+    pub avoid_regions: Option<Vec<u32>>,
+    /// Ship used to compute the fuel burnt by jumps
+    pub ship: Option<data::Ship>,
+    /// Limit jumps to what a fuel tank can hold, refuelling in some systems.
+    /// Not available with the bidirectional algorithm or alternatives.
+    pub tank: Option<data::FuelTank>,
+    /// Number of alternative routes to return along with the best one
+    pub alternatives: Option<usize>,
+    /// Search timeout in milliseconds, 25000 at most
+    pub timeout_ms: Option<u64>,
+    /// Most systems the searches of the request may expand, all together,
+    /// before giving up
+    pub max_visited: Option<u64>,
+    /// Unit of the distances in the result, light-years by default
+    pub unit: Option<data::DistanceUnit>,
     pub smart_gates: Vec<SmartGateLink>,
 }
 
@@ -78,41 +299,27 @@ pub struct PathPayload {
     post,
     path = "/path",
     responses(
-        (status = 200, description = "Success", body = data::PathResult),
+        (status = 200, description = "Success, or a SearchError if the search failed", body = data::PathResult),
+        (status = 400, description = "Invalid ship, tank, weights or avoided system, or a tank with the bidirectional algorithm or alternatives"),
+        (status = 404, description = "Unknown system"),
     ),
     request_body(content = PathPayload, description = "The payload to calculate the path"),
 )]
 #[rocket::post("/path", data = "<payload>")]
 pub async fn calc_path(
-    star_map: &State<data::StarMap>,
+    star_map: &State<Arc<data::StarMap>>,
     semaphore: &State<Arc<Semaphore>>,
     payload: Json<PathPayload>,
-) -> Json<data::PathResult> {
+) -> Result<(ContentType, ByteStream![Vec<u8>]), CustomError> {
     info!("Payload: {:?}", payload);
-    let _permit = semaphore
-        .acquire()
+    let permit = Arc::clone(semaphore.inner())
+        .acquire_owned()
         .await
         .expect("Max concurrent requests reached, try again later");
 
     let start_time = std::time::Instant::now();
 
-    let mut smart_gates_map: data::SmartGatesMap = HashMap::new();
-    for smart_gate in &payload.smart_gates {
-        let from_id = tools::system_id_to_u16(smart_gate.from).unwrap();
-        let to_id = tools::system_id_to_u16(smart_gate.to).unwrap();
-        if !smart_gates_map.contains_key(&from_id) {
-            smart_gates_map.insert(from_id, Vec::new());
-        }
-        smart_gates_map
-            .get_mut(&from_id)
-            .unwrap()
-            .push(data::Connection {
-                conn_type: data::ConnType::SmartGate,
-                distance: smart_gate.distance,
-                target: to_id,
-                id: smart_gate.id,
-            });
-    }
+    let smart_gates_map = smart_gates_map(&payload.smart_gates);
 
     let elapsed = start_time.elapsed().as_millis();
     info!(
@@ -121,32 +328,337 @@ pub async fn calc_path(
         elapsed
     );
 
-    let start = star_map
-        .get(&tools::system_id_to_u16(payload.from).unwrap())
-        .unwrap();
-    let end = star_map
-        .get(&tools::system_id_to_u16(payload.to).unwrap())
-        .unwrap();
-
-    let path = path::calc_path(
-        &star_map,
-        &smart_gates_map,
-        start,
-        end,
-        payload.jump_distance,
-        payload.optimize.unwrap(),
-        Some(25),
-    );
-    info!("Path: {:?}", path);
+    let start = get_star(star_map, &payload.from)?.id;
+    let end = get_star(star_map, &payload.to)?.id;
+
+    let cancel = astar::CancelToken::default();
+    let options = data::PathOptions {
+        jump_distance: payload.jump_distance,
+        optimize: payload.optimize.unwrap(),
+        weights: cost_weights(&payload.weights)?,
+        algorithm: payload.algorithm.unwrap_or_default(),
+        avoid: avoid(
+            star_map,
+            &payload.avoid_systems,
+            &payload.avoid_connections,
+            &payload.avoid_regions,
+        )?,
+        ship: ship_fuel(&payload.ship)?,
+        tank: fuel_tank(&payload.tank)?,
+        limits: search_limits(payload.timeout_ms, payload.max_visited, &cancel),
+        unit: payload.unit.unwrap_or_default(),
+    };
+    let alternatives = payload.alternatives.unwrap_or(0);
+    options
+        .validate(alternatives)
+        .map_err(|err| CustomError(Status::BadRequest, err.to_string()))?;
+    let star_map = Arc::clone(star_map);
+    Ok(search_response(permit, cancel, move || {
+        path::calc_alternatives(
+            &star_map,
+            &smart_gates_map,
+            &star_map[&start],
+            &star_map[&end],
+            &options,
+            alternatives,
+        )
+    }))
+}
+
+// POST /api/itinerary
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ItineraryPayload {
+    /// Systems to visit, in order, by id or name
+    pub systems: Vec<data::SystemRef>,
+    pub jump_distance: u16,
+    pub optimize: Option<data::PathOptimize>,
+    /// Weights of the weighted optimization mode
+    pub weights: Option<data::CostWeights>,
+    pub algorithm: Option<data::PathAlgorithm>,
+    /// Systems the route must not go through
+    pub avoid_systems: Option<Vec<u32>>,
+    /// Connections the route must not use
+    pub avoid_connections: Option<Vec<u32>>,
+    /// Regions the route must not go through
+    pub avoid_regions: Option<Vec<u32>>,
+    /// Ship used to compute the fuel burnt by jumps
+    pub ship: Option<data::Ship>,
+    /// Limit jumps to what a fuel tank can hold, refuelling in some systems.
+    /// Not available with the bidirectional algorithm.
+    pub tank: Option<data::FuelTank>,
+    /// Search timeout in milliseconds, 25000 at most
+    pub timeout_ms: Option<u64>,
+    /// Most systems the searches of the request may expand, all together,
+    /// before giving up
+    pub max_visited: Option<u64>,
+    /// Unit of the distances in the result, light-years by default
+    pub unit: Option<data::DistanceUnit>,
+    pub smart_gates: Vec<SmartGateLink>,
+}
 
-    Json(path)
+/// Calculate a trip through several systems
+///
+/// Runs one path search per leg between consecutive systems, and returns
+/// the combined route with the boundaries and stats of every leg
+#[utoipa::path(
+    post,
+    path = "/itinerary",
+    responses(
+        (status = 200, description = "Success, or a SearchError if the search failed", body = data::ItineraryResult),
+        (status = 400, description = "Less than two systems, invalid ship, tank, weights or avoided system, or a tank with the bidirectional algorithm"),
+        (status = 404, description = "Unknown system"),
+    ),
+    request_body(content = ItineraryPayload, description = "The payload to calculate the itinerary"),
+)]
+#[rocket::post("/itinerary", data = "<payload>")]
+pub async fn calc_itinerary(
+    star_map: &State<Arc<data::StarMap>>,
+    semaphore: &State<Arc<Semaphore>>,
+    payload: Json<ItineraryPayload>,
+) -> Result<(ContentType, ByteStream![Vec<u8>]), CustomError> {
+    info!("Payload: {:?}", payload);
+    if payload.systems.len() < 2 {
+        return Err(CustomError(
+            Status::BadRequest,
+            String::from("An itinerary needs at least two systems"),
+        ));
+    }
+    let stops = payload
+        .systems
+        .iter()
+        .map(|system| get_star(star_map, system).map(|star| star.id))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let permit = Arc::clone(semaphore.inner())
+        .acquire_owned()
+        .await
+        .expect("Max concurrent requests reached, try again later");
+
+    let smart_gates_map = smart_gates_map(&payload.smart_gates);
+    let cancel = astar::CancelToken::default();
+    let options = data::PathOptions {
+        jump_distance: payload.jump_distance,
+        optimize: payload.optimize.unwrap_or(data::PathOptimize::Fuel),
+        weights: cost_weights(&payload.weights)?,
+        algorithm: payload.algorithm.unwrap_or_default(),
+        avoid: avoid(
+            star_map,
+            &payload.avoid_systems,
+            &payload.avoid_connections,
+            &payload.avoid_regions,
+        )?,
+        ship: ship_fuel(&payload.ship)?,
+        tank: fuel_tank(&payload.tank)?,
+        limits: search_limits(payload.timeout_ms, payload.max_visited, &cancel),
+        unit: payload.unit.unwrap_or_default(),
+    };
+    options
+        .validate(0)
+        .map_err(|err| CustomError(Status::BadRequest, err.to_string()))?;
+    let star_map = Arc::clone(star_map);
+    Ok(search_response(permit, cancel, move || {
+        let stops: Vec<&data::Star> = stops.iter().map(|id| &star_map[id]).collect();
+        path::calc_itinerary(&star_map, &smart_gates_map, &stops, &options)
+    }))
+}
+
+// POST /api/tour
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TourPayload {
+    /// Systems to visit, by id or name, the first one is where the tour starts
+    pub systems: Vec<data::SystemRef>,
+    /// Come back to the first system at the end of the tour
+    pub round_trip: Option<bool>,
+    pub jump_distance: u16,
+    pub optimize: Option<data::PathOptimize>,
+    /// Weights of the weighted optimization mode
+    pub weights: Option<data::CostWeights>,
+    pub algorithm: Option<data::PathAlgorithm>,
+    /// Systems the route must not go through
+    pub avoid_systems: Option<Vec<u32>>,
+    /// Connections the route must not use
+    pub avoid_connections: Option<Vec<u32>>,
+    /// Regions the route must not go through
+    pub avoid_regions: Option<Vec<u32>>,
+    /// Ship used to compute the fuel burnt by jumps
+    pub ship: Option<data::Ship>,
+    /// Limit jumps to what a fuel tank can hold, refuelling in some systems.
+    /// Not available with the bidirectional algorithm.
+    pub tank: Option<data::FuelTank>,
+    /// Search timeout in milliseconds, 25000 at most
+    pub timeout_ms: Option<u64>,
+    /// Most systems the searches of the request may expand, all together,
+    /// before giving up
+    pub max_visited: Option<u64>,
+    /// Unit of the distances in the result, light-years by default
+    pub unit: Option<data::DistanceUnit>,
+    pub smart_gates: Vec<SmartGateLink>,
+}
+
+/// Most systems accepted in a tour, every pair of them is searched
+const MAX_TOUR_SYSTEMS: usize = 30;
+
+/// Find the cheapest order to visit a set of systems
+///
+/// Starts from the first system, visits all the others in the cheapest
+/// order and optionally comes back. Returns the order with the routes
+/// between consecutive systems
+#[utoipa::path(
+    post,
+    path = "/tour",
+    responses(
+        (status = 200, description = "Success, or a SearchError if the search failed", body = data::TourResult),
+        (status = 400, description = "Too few or too many systems, invalid ship, tank, weights or avoided system, or a tank with the bidirectional algorithm"),
+        (status = 404, description = "Unknown system"),
+    ),
+    request_body(content = TourPayload, description = "The payload to calculate the tour"),
+)]
+#[rocket::post("/tour", data = "<payload>")]
+pub async fn calc_tour(
+    star_map: &State<Arc<data::StarMap>>,
+    semaphore: &State<Arc<Semaphore>>,
+    payload: Json<TourPayload>,
+) -> Result<(ContentType, ByteStream![Vec<u8>]), CustomError> {
+    info!("Payload: {:?}", payload);
+    if payload.systems.len() < 2 || payload.systems.len() > MAX_TOUR_SYSTEMS {
+        return Err(CustomError(
+            Status::BadRequest,
+            format!("A tour needs between 2 and {} systems", MAX_TOUR_SYSTEMS),
+        ));
+    }
+    let stops = payload
+        .systems
+        .iter()
+        .map(|system| get_star(star_map, system).map(|star| star.id))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let permit = Arc::clone(semaphore.inner())
+        .acquire_owned()
+        .await
+        .expect("Max concurrent requests reached, try again later");
+
+    let smart_gates_map = smart_gates_map(&payload.smart_gates);
+    let cancel = astar::CancelToken::default();
+    let options = data::PathOptions {
+        jump_distance: payload.jump_distance,
+        optimize: payload.optimize.unwrap_or(data::PathOptimize::Fuel),
+        weights: cost_weights(&payload.weights)?,
+        algorithm: payload.algorithm.unwrap_or_default(),
+        avoid: avoid(
+            star_map,
+            &payload.avoid_systems,
+            &payload.avoid_connections,
+            &payload.avoid_regions,
+        )?,
+        ship: ship_fuel(&payload.ship)?,
+        tank: fuel_tank(&payload.tank)?,
+        limits: search_limits(payload.timeout_ms, payload.max_visited, &cancel),
+        unit: payload.unit.unwrap_or_default(),
+    };
+    options
+        .validate(0)
+        .map_err(|err| CustomError(Status::BadRequest, err.to_string()))?;
+    let star_map = Arc::clone(star_map);
+    let round_trip = payload.round_trip.unwrap_or(false);
+    Ok(search_response(permit, cancel, move || {
+        let stops: Vec<&data::Star> = stops.iter().map(|id| &star_map[id]).collect();
+        tour::calc_tour(&star_map, &smart_gates_map, &stops, &options, round_trip)
+    }))
+}
+
+// POST /api/pareto
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ParetoPayload {
+    /// Start system, by id or name
+    pub from: data::SystemRef,
+    /// End system, by id or name
+    pub to: data::SystemRef,
+    pub jump_distance: u16,
+    /// Systems the route must not go through
+    pub avoid_systems: Option<Vec<u32>>,
+    /// Connections the route must not use
+    pub avoid_connections: Option<Vec<u32>>,
+    /// Regions the route must not go through
+    pub avoid_regions: Option<Vec<u32>>,
+    /// Ship used to compute the fuel burnt by jumps
+    pub ship: Option<data::Ship>,
+    /// Search timeout in milliseconds, 25000 at most
+    pub timeout_ms: Option<u64>,
+    /// Most systems the searches of the request may expand, all together,
+    /// before giving up
+    pub max_visited: Option<u64>,
+    /// Unit of the distances in the result, light-years by default
+    pub unit: Option<data::DistanceUnit>,
+    pub smart_gates: Vec<SmartGateLink>,
+}
+
+/// Find the trade-offs between fuel, distance and hops
+///
+/// Returns every route that no other route beats on fuel, distance and
+/// hops at once, with all three metrics
+#[utoipa::path(
+    post,
+    path = "/pareto",
+    responses(
+        (status = 200, description = "Success, or a SearchError if the search failed", body = data::ParetoResult),
+        (status = 400, description = "Invalid ship or avoided system"),
+        (status = 404, description = "Unknown system"),
+    ),
+    request_body(content = ParetoPayload, description = "The payload to calculate the routes"),
+)]
+#[rocket::post("/pareto", data = "<payload>")]
+pub async fn calc_pareto(
+    star_map: &State<Arc<data::StarMap>>,
+    semaphore: &State<Arc<Semaphore>>,
+    payload: Json<ParetoPayload>,
+) -> Result<(ContentType, ByteStream![Vec<u8>]), CustomError> {
+    info!("Payload: {:?}", payload);
+    let start = get_star(star_map, &payload.from)?.id;
+    let end = get_star(star_map, &payload.to)?.id;
+
+    let permit = Arc::clone(semaphore.inner())
+        .acquire_owned()
+        .await
+        .expect("Max concurrent requests reached, try again later");
+
+    let smart_gates_map = smart_gates_map(&payload.smart_gates);
+    let cancel = astar::CancelToken::default();
+    let options = data::PathOptions {
+        jump_distance: payload.jump_distance,
+        optimize: data::PathOptimize::Fuel,
+        weights: data::CostWeights::default(),
+        algorithm: data::PathAlgorithm::default(),
+        avoid: avoid(
+            star_map,
+            &payload.avoid_systems,
+            &payload.avoid_connections,
+            &payload.avoid_regions,
+        )?,
+        ship: ship_fuel(&payload.ship)?,
+        tank: None,
+        limits: search_limits(payload.timeout_ms, payload.max_visited, &cancel),
+        unit: payload.unit.unwrap_or_default(),
+    };
+    let star_map = Arc::clone(star_map);
+    Ok(search_response(permit, cancel, move || {
+        pareto::calc_pareto(
+            &star_map,
+            &smart_gates_map,
+            &star_map[&start],
+            &star_map[&end],
+            &options,
+        )
+    }))
 }
 
 // POST /api/near
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct NearPayload {
-    pub from: u32,
+    /// Star, by id or name
+    pub from: data::SystemRef,
     pub distance: u16,
+    /// Unit of the distances in the result, light-years by default
+    pub unit: Option<data::DistanceUnit>,
 }
 
 /// Find the nearest stars to a given star
@@ -157,27 +669,81 @@ pub struct NearPayload {
     path = "/near",
     responses(
         (status = 200, description = "Success", body = data::NearResult),
+        (status = 404, description = "Unknown system"),
     ),
     request_body(content = NearPayload, description = "The payload to calculate the nearest stars"),
 )]
 #[rocket::post("/near", data = "<payload>")]
 pub fn calc_near(
-    star_map: &State<data::StarMap>,
+    star_map: &State<Arc<data::StarMap>>,
     payload: Json<NearPayload>,
-) -> Json<data::NearResult> {
+) -> Result<Json<data::NearResult>, CustomError> {
     info!("Payload: {:?}", payload);
     let start_time = std::time::Instant::now();
 
-    let star = star_map
-        .get(&tools::system_id_to_u16(payload.from).unwrap())
-        .unwrap();
-    let result = search::near(&star_map, star, payload.distance);
-    Json(result)
+    let star = get_star(star_map, &payload.from)?;
+    let result = search::near(
+        star_map,
+        star,
+        payload.distance,
+        payload.unit.unwrap_or_default(),
+    );
+    Ok(Json(result))
+}
+
+// POST /api/systems
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SystemsPayload {
+    pub constellation: Option<u32>,
+    pub region: Option<u32>,
+}
+
+/// List the systems of a constellation or a region
+///
+/// Returns the systems of the constellation and/or the region, by id
+#[utoipa::path(
+    post,
+    path = "/systems",
+    responses(
+        (status = 200, description = "Success", body = data::SystemsResult),
+        (status = 400, description = "Neither a constellation nor a region"),
+    ),
+    request_body(content = SystemsPayload, description = "The constellation or region to list"),
+)]
+#[rocket::post("/systems", data = "<payload>")]
+pub fn calc_systems(
+    star_map: &State<Arc<data::StarMap>>,
+    payload: Json<SystemsPayload>,
+) -> Result<Json<data::SystemsResult>, CustomError> {
+    info!("Payload: {:?}", payload);
+    if payload.constellation.is_none() && payload.region.is_none() {
+        return Err(CustomError(
+            Status::BadRequest,
+            String::from("Either constellation or region is required"),
+        ));
+    }
+    let result = search::systems(star_map, payload.constellation, payload.region);
+    Ok(Json(result))
 }
 
 #[derive(OpenApi)]
 #[openapi(
-    paths(calc_path, calc_near),
-    components(schemas(data::PathResult, data::NearResult))
+    paths(
+        calc_path,
+        calc_itinerary,
+        calc_tour,
+        calc_pareto,
+        calc_near,
+        calc_systems
+    ),
+    components(schemas(
+        data::PathResult,
+        data::ItineraryResult,
+        data::TourResult,
+        data::ParetoResult,
+        data::NearResult,
+        data::SystemsResult,
+        SearchError
+    ))
 )]
 pub struct ApiDoc;
@@ -6,6 +6,8 @@ use serde::Serialize;
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
 use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use indexmap::IndexMap;
@@ -25,15 +27,82 @@ pub struct Stats {
 pub enum PathFindResult<N, C> {
     Found((Vec<N>, C, Stats)),
     NotFound(Stats),
+    /// A search limit was reached before finding the path
     Timeout(Stats),
 }
 
+/// Flag shared with a running search, so another thread can stop it
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, AtomicOrdering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(AtomicOrdering::Relaxed)
+    }
+}
+
+/// Nodes expanded by every search sharing the same limits, so that the
+/// searches of a request spend one budget together
+#[derive(Debug, Clone, Default)]
+pub struct VisitCounter(Arc<AtomicU64>);
+
+impl VisitCounter {
+    pub fn add(&self) {
+        self.0.fetch_add(1, AtomicOrdering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(AtomicOrdering::Relaxed)
+    }
+}
+
+/// When a search gives up. Clones share the visited nodes budget.
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimits {
+    pub timeout: Option<Duration>,
+    /// Most nodes the searches may expand, all together
+    pub max_visited: Option<u64>,
+    pub cancel: Option<CancelToken>,
+    pub visited: VisitCounter,
+}
+
+impl SearchLimits {
+    pub fn with_timeout(timeout: Duration) -> Self {
+        SearchLimits {
+            timeout: Some(timeout),
+            ..Default::default()
+        }
+    }
+
+    /// Whether a search started at `start_time` must stop
+    pub fn reached(&self, start_time: Instant) -> bool {
+        self.timeout
+            .is_some_and(|timeout| start_time.elapsed() >= timeout)
+            || self
+                .max_visited
+                .is_some_and(|max| self.visited.get() >= max)
+            || self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Limits of a follow-up search, once `elapsed` was spent by previous ones
+    pub fn remaining(&self, elapsed: Duration) -> SearchLimits {
+        SearchLimits {
+            timeout: self.timeout.map(|timeout| timeout.saturating_sub(elapsed)),
+            ..self.clone()
+        }
+    }
+}
+
 pub fn astar<N, C, FN, IN, FH, FS>(
     start: &N,
     mut successors: FN,
     mut heuristic: FH,
     mut success: FS,
-    timeout: Option<u64>,
+    limits: &SearchLimits,
 ) -> PathFindResult<N, C>
 where
     N: Eq + Hash + Clone,
@@ -59,11 +128,12 @@ where
     let mut parents: FxIndexMap<N, (usize, C)> = FxIndexMap::default();
     parents.insert(start.clone(), (usize::MAX, Zero::zero()));
     while let Some(SmallestCostHolder { cost, index, .. }) = to_see.pop() {
-        if timeout.is_some() && start_time.elapsed().as_secs() >= timeout.unwrap() {
+        if limits.reached(start_time) {
             stats.total_time = start_time.elapsed();
             return PathFindResult::Timeout(stats);
         }
         stats.visited += 1;
+        limits.visited.add();
         let successors = {
             let (node, &(_, c)) = parents.get_index(index).unwrap(); // Cannot fail
             if success(node) {
@@ -118,6 +188,176 @@ where
     PathFindResult::NotFound(stats)
 }
 
+/// Bidirectional A*: search forward from `start` and backward from `end`
+/// at the same time, and stitch both halves together where they meet.
+///
+/// - `successors` gives the outgoing edges of a node
+/// - `predecessors` gives the incoming edges of a node (the reverse graph)
+/// - `heuristic` estimates the cost from a node to `end`
+/// - `reverse_heuristic` estimates the cost from `start` to a node
+///
+/// The search stops as soon as one frontier cannot improve on the best
+/// meeting point found so far, which keeps the result optimal as long as
+/// both heuristics are admissible and consistent.
+#[allow(clippy::too_many_arguments)]
+pub fn bidirectional_astar<N, C, FN, IN, FP, IP, FH, FR>(
+    start: &N,
+    end: &N,
+    mut successors: FN,
+    mut predecessors: FP,
+    mut heuristic: FH,
+    mut reverse_heuristic: FR,
+    limits: &SearchLimits,
+) -> PathFindResult<N, C>
+where
+    N: Eq + Hash + Clone,
+    C: Zero + Ord + Copy,
+    FN: FnMut(&N) -> IN,
+    IN: IntoIterator<Item = (N, C)>,
+    FP: FnMut(&N) -> IP,
+    IP: IntoIterator<Item = (N, C)>,
+    FH: FnMut(&N) -> C,
+    FR: FnMut(&N) -> C,
+{
+    let mut stats = Stats {
+        total_time: Duration::from_secs(0),
+        successors_spend: Duration::from_secs(0),
+        loop_spend: Duration::from_secs(0),
+        visited: 0,
+    };
+    let start_time = Instant::now();
+    if start == end {
+        stats.total_time = start_time.elapsed();
+        return PathFindResult::Found((vec![start.clone()], Zero::zero(), stats));
+    }
+
+    // Index 0 is the forward search, index 1 the backward one
+    let mut to_see = [BinaryHeap::new(), BinaryHeap::new()];
+    let mut parents: [FxIndexMap<N, (usize, C)>; 2] =
+        [FxIndexMap::default(), FxIndexMap::default()];
+    for (side, root) in [start, end].into_iter().enumerate() {
+        to_see[side].push(SmallestCostHolder {
+            estimated_cost: Zero::zero(),
+            cost: Zero::zero(),
+            index: 0,
+        });
+        parents[side].insert(root.clone(), (usize::MAX, Zero::zero()));
+    }
+    // Best complete path found so far: (cost, forward index, backward index)
+    let mut best: Option<(C, usize, usize)> = None;
+
+    loop {
+        if limits.reached(start_time) {
+            stats.total_time = start_time.elapsed();
+            return PathFindResult::Timeout(stats);
+        }
+        // Expand the smaller frontier first, it is the cheapest to grow
+        let side = match (to_see[0].is_empty(), to_see[1].is_empty()) {
+            (true, true) => break,
+            (false, true) => 0,
+            (true, false) => 1,
+            (false, false) => usize::from(to_see[1].len() < to_see[0].len()),
+        };
+        let Some(SmallestCostHolder {
+            estimated_cost,
+            cost,
+            index,
+        }) = to_see[side].pop()
+        else {
+            break;
+        };
+        // Nothing left on this side can lead to a cheaper meeting point
+        if matches!(best, Some((mu, _, _)) if estimated_cost >= mu) {
+            break;
+        }
+        stats.visited += 1;
+        limits.visited.add();
+        let neighbours: Vec<(N, C)> = {
+            // Cannot fail
+            let (node, &(_, c)) = parents[side].get_index(index).unwrap();
+            // We may have inserted a node several time into the binary heap if we found
+            // a better way to access it. Ensure that we are currently dealing with the
+            // best path and discard the others.
+            if cost > c {
+                continue;
+            }
+            let start_time = Instant::now();
+            let r = if side == 0 {
+                successors(node).into_iter().collect()
+            } else {
+                predecessors(node).into_iter().collect()
+            };
+            stats.successors_spend += start_time.elapsed();
+            r
+        };
+
+        let start_time = Instant::now();
+        let mut new_nodes = Vec::new();
+        for (neighbour, move_cost) in neighbours {
+            let new_cost = cost + move_cost;
+            let h;
+            let n;
+            match parents[side].entry(neighbour) {
+                Vacant(e) => {
+                    h = if side == 0 {
+                        heuristic(e.key())
+                    } else {
+                        reverse_heuristic(e.key())
+                    };
+                    n = e.index();
+                    e.insert((index, new_cost));
+                }
+                Occupied(mut e) => {
+                    if e.get().1 > new_cost {
+                        h = if side == 0 {
+                            heuristic(e.key())
+                        } else {
+                            reverse_heuristic(e.key())
+                        };
+                        n = e.index();
+                        e.insert((index, new_cost));
+                    } else {
+                        continue;
+                    }
+                }
+            }
+            // Did we reach a node already labelled by the other search?
+            let node = parents[side].get_index(n).unwrap().0;
+            if let Some((other, _, &(_, other_cost))) = parents[1 - side].get_full(node) {
+                let total = new_cost + other_cost;
+                if best.is_none_or(|(mu, _, _)| total < mu) {
+                    best = Some(if side == 0 {
+                        (total, n, other)
+                    } else {
+                        (total, other, n)
+                    });
+                }
+            }
+            new_nodes.push(SmallestCostHolder {
+                estimated_cost: new_cost + h,
+                cost: new_cost,
+                index: n,
+            });
+        }
+        stats.loop_spend += start_time.elapsed();
+        to_see[side].extend(new_nodes);
+    }
+
+    stats.total_time = start_time.elapsed();
+    match best {
+        Some((cost, forward, backward)) => {
+            let mut path = reverse_path(&parents[0], |&(p, _)| p, forward);
+            // The backward half runs from `end` to the meeting node, flip it
+            // and drop the meeting node which is already in the forward half
+            let mut tail = reverse_path(&parents[1], |&(p, _)| p, backward);
+            tail.reverse();
+            path.extend(tail.into_iter().skip(1));
+            PathFindResult::Found((path, cost, stats))
+        }
+        None => PathFindResult::NotFound(stats),
+    }
+}
+
 #[allow(clippy::needless_collect)]
 fn reverse_path<N, V, F>(parents: &FxIndexMap<N, V>, mut parent: F, start: usize) -> Vec<N>
 where
@@ -165,3 +405,108 @@ impl<K: Ord> Ord for SmallestCostHolder<K> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Small directed graph with a one-way shortcut 1 -> 4
+    fn edges(node: &u32) -> Vec<(u32, i64)> {
+        match node {
+            0 => vec![(1, 2), (2, 4)],
+            1 => vec![(0, 2), (3, 4), (4, 1)],
+            2 => vec![(0, 4), (3, 1)],
+            3 => vec![(1, 4), (2, 1), (5, 2)],
+            4 => vec![(5, 6)],
+            5 => vec![(3, 2), (4, 6)],
+            _ => vec![],
+        }
+    }
+
+    fn reverse_edges(node: &u32) -> Vec<(u32, i64)> {
+        (0..6)
+            .flat_map(|from| {
+                edges(&from)
+                    .into_iter()
+                    .filter(|(to, _)| to == node)
+                    .map(move |(_, c)| (from, c))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bidirectional_matches_astar() {
+        for start in 0..6 {
+            for end in 0..6 {
+                let limits = SearchLimits::default();
+                let forward = astar(&start, edges, |_| 0, |n| *n == end, &limits);
+                let both =
+                    bidirectional_astar(&start, &end, edges, reverse_edges, |_| 0, |_| 0, &limits);
+                match (forward, both) {
+                    (PathFindResult::Found((_, c1, _)), PathFindResult::Found((p, c2, _))) => {
+                        assert_eq!(c1, c2, "{} -> {}", start, end);
+                        assert_eq!(p.first(), Some(&start));
+                        assert_eq!(p.last(), Some(&end));
+                    }
+                    (PathFindResult::NotFound(_), PathFindResult::NotFound(_)) => {}
+                    _ => panic!("{} -> {}: results differ", start, end),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bidirectional_one_way() {
+        // 4 can only be left towards 5
+        let limits = SearchLimits::default();
+        match bidirectional_astar(&4, &0, edges, reverse_edges, |_| 0, |_| 0, &limits) {
+            PathFindResult::Found((path, cost, _)) => {
+                assert_eq!(path, vec![4, 5, 3, 2, 0]);
+                assert_eq!(cost, 13);
+            }
+            _ => panic!("Expected to find a path"),
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let budget = SearchLimits {
+            max_visited: Some(2),
+            ..Default::default()
+        };
+        assert!(matches!(
+            astar(&4, edges, |_| 0, |n| *n == 0, &budget),
+            PathFindResult::Timeout(Stats { visited: 2, .. })
+        ));
+        // The budget is shared with the following searches
+        assert!(matches!(
+            bidirectional_astar(&4, &0, edges, reverse_edges, |_| 0, |_| 0, &budget.clone()),
+            PathFindResult::Timeout(Stats { visited: 0, .. })
+        ));
+
+        let cancel = CancelToken::default();
+        let cancelled = SearchLimits {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            astar(&4, edges, |_| 0, |n| *n == 0, &cancelled),
+            PathFindResult::Found(_)
+        ));
+        cancel.cancel();
+        assert!(matches!(
+            astar(&4, edges, |_| 0, |n| *n == 0, &cancelled),
+            PathFindResult::Timeout(_)
+        ));
+        assert!(matches!(
+            bidirectional_astar(&4, &0, edges, reverse_edges, |_| 0, |_| 0, &cancelled),
+            PathFindResult::Timeout(_)
+        ));
+
+        let expired = SearchLimits::with_timeout(Duration::ZERO);
+        assert!(matches!(
+            astar(&4, edges, |_| 0, |n| *n == 0, &expired),
+            PathFindResult::Timeout(_)
+        ));
+    }
+}
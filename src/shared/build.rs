@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+
+use indicatif::ParallelProgressIterator;
+use log::info;
+use rayon::prelude::*;
+use uom::si::f64::*;
+
+use super::data::*;
+use super::raw;
+use super::tools;
+
+/// Build the star map from the raw data, on the current rayon thread pool.
+///
+/// Every phase runs in parallel, and the output only depends on the input:
+/// gates and links get their ids in the order of the raw jumps, gates are
+/// told apart by the constellations and regions they link, and jump types
+/// other than gates become special links. Jumps are not stored,
+/// the star map computes them when searching. Names are optional, and
+/// names of unknown systems are ignored. The raw data should go through
+/// `validate` first, the build fails on records it would have skipped.
+pub fn build_star_map(
+    raw_star_map: &raw::RawStarMap,
+    raw_names: &[raw::RawStar],
+    source: &BuildSource,
+) -> anyhow::Result<StarMap> {
+    info!("Building stars");
+    let mut stars: Vec<Star> = raw_star_map
+        .solar_systems
+        .par_iter()
+        .progress_count(raw_star_map.solar_systems.len() as u64)
+        .map(|(id, raw_star)| {
+            Ok(Star::new(
+                tools::system_id_to_u16(id.parse()?)?,
+                raw_star.center[0],
+                raw_star.center[1],
+                raw_star.center[2],
+            )
+            .with_hierarchy(raw_star.constellation_id, raw_star.region_id))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    stars.par_sort_unstable_by_key(|star| star.id);
+    let positions: HashMap<SolarSystemId, usize> = stars
+        .iter()
+        .enumerate()
+        .map(|(i, star)| (star.id, i))
+        .collect();
+
+    info!("Building connections from npc gates and links");
+    let links: Vec<Vec<(usize, Connection)>> = raw_star_map
+        .jumps
+        .par_iter()
+        .progress_count(raw_star_map.jumps.len() as u64)
+        .map(|raw_jump| {
+            let position = |id: u32| {
+                positions
+                    .get(&tools::system_id_to_u16(id)?)
+                    .copied()
+                    .ok_or_else(|| anyhow::anyhow!("Gate to unknown system {}", id))
+            };
+            let from = position(raw_jump.from_system_id)?;
+            let to = position(raw_jump.to_system_id)?;
+            let conn_type = if raw_jump.one_way {
+                ConnType::OneWay
+            } else if matches!(raw_jump.jump_type, 0 | 1) {
+                ConnType::stargate(&stars[from], &stars[to])
+            } else {
+                ConnType::Special
+            };
+            let distance = DistanceUnit::LightYear.get(stars[from].distance(&stars[to]));
+            let connection = |target: &Star| Connection {
+                id: 0, // Numbered below
+                conn_type: conn_type.clone(),
+                jump_type: raw_jump.jump_type,
+                distance,
+                target: target.id,
+            };
+            let mut links = vec![(from, connection(&stars[to]))];
+            if conn_type.two_way() {
+                links.push((to, connection(&stars[from])));
+            }
+            Ok(links)
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let mut connections: Vec<Vec<Connection>> = vec![Vec::new(); stars.len()];
+    let mut conn_count: ConnectionId = 0;
+    for (from, mut connection) in links.into_iter().flatten() {
+        connection.id = conn_count;
+        conn_count += 1;
+        connections[from].push(connection);
+    }
+
+    info!("Found {} gates and links", conn_count);
+
+    info!("Sorting connections");
+    // sort gates by distance, ties are broken by id so the order does
+    // not depend on the sort
+    connections
+        .par_iter_mut()
+        .progress_count(stars.len() as u64)
+        .for_each(|connections| {
+            connections.sort_unstable_by(|a, b| a.cmp(b).then(a.id.cmp(&b.id)))
+        });
+
+    info!("Naming stars");
+    let mut names: Vec<String> = vec![String::new(); stars.len()];
+    for raw_name in raw_names {
+        let position = u32::try_from(raw_name.solar_system_id)
+            .ok()
+            .and_then(|id| tools::system_id_to_u16(id).ok())
+            // Ids outside the known ranges would alias another system
+            .filter(|&id| u64::from(tools::u16_to_system_id(id)) == raw_name.solar_system_id)
+            .and_then(|id| positions.get(&id));
+        match position {
+            Some(&i) => names[i] = raw_name.solar_system_name.trim().to_string(),
+            None => info!("{} is an unknown system", raw_name.solar_system_id),
+        }
+    }
+
+    info!("Building the star map file");
+    let entries = stars
+        .into_iter()
+        .zip(names)
+        .zip(connections)
+        .map(|((star, name), connections)| StarEntry {
+            star,
+            name,
+            connections,
+        })
+        .collect();
+    StarMap::new(entries, source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::LY;
+    use super::*;
+
+    fn raw_star_map() -> raw::RawStarMap {
+        let solar_systems = (0..200u32)
+            .map(|i| {
+                let center = [
+                    (i % 7) as f64 * 40.0 * LY,
+                    (i % 11) as f64 * 30.0 * LY,
+                    (i / 11) as f64 * 25.0 * LY,
+                ];
+                let raw_star = raw::RawSolarSystem {
+                    center,
+                    constellation_id: 20000000 + i / 10,
+                    region_id: 10000000 + i / 50,
+                };
+                ((30000000 + i).to_string(), raw_star)
+            })
+            .collect();
+        let jumps = (0..199u32)
+            .step_by(3)
+            .map(|i| raw::RawJump {
+                from_system_id: 30000000 + i,
+                jump_type: (i / 3 % 3) as u8,
+                to_system_id: 30000001 + i,
+                one_way: i % 4 == 0,
+            })
+            .collect();
+        raw::RawStarMap {
+            jumps,
+            solar_systems,
+        }
+    }
+
+    fn raw_names() -> Vec<raw::RawStar> {
+        (0..200u64)
+            .map(|i| raw::RawStar {
+                solar_system_id: 30000000 + i,
+                solar_system_name: format!("System-{}", i),
+            })
+            .collect()
+    }
+
+    fn star_map() -> StarMap {
+        build_star_map(&raw_star_map(), &raw_names(), &BuildSource::default()).unwrap()
+    }
+
+    #[test]
+    fn test_build_is_deterministic() {
+        let raw_star_map = raw_star_map();
+        let raw_names = raw_names();
+        let builds: Vec<StarMap> = [1, 4]
+            .into_iter()
+            .map(|threads| {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap();
+                pool.install(|| build_star_map(&raw_star_map, &raw_names, &BuildSource::default()))
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(builds[0].as_bytes(), builds[1].as_bytes());
+        assert_eq!(builds[0].len(), 200);
+    }
+
+    /// Removes the file when dropped, so failed assertions don't leave it behind
+    struct TempFile(std::path::PathBuf);
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_header_integrity() {
+        let star_map = star_map();
+        // The saved file is used as is
+        let file =
+            TempFile(std::env::temp_dir().join(format!("starmap-{}.bin", std::process::id())));
+        let path = file.0.to_str().unwrap();
+        save_star_map(&star_map, path).unwrap();
+        assert_eq!(get_star_map(path).unwrap().as_bytes(), star_map.as_bytes());
+        assert_eq!(get_star_map_info(path).unwrap().stars, 200);
+        let mut bytes = std::fs::read(path).unwrap();
+        std::fs::write(path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(get_star_map(path).is_err());
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        std::fs::write(path, &bytes).unwrap();
+        let err = get_star_map(path).unwrap_err();
+        assert!(err.to_string().contains("corrupted"), "{}", err);
+        bytes[0] ^= 1;
+        std::fs::write(path, &bytes).unwrap();
+        assert!(get_star_map_info(path).is_err());
+    }
+
+    #[test]
+    fn test_link_kinds() {
+        let star_map = star_map();
+        // Every jump type is kept with its raw type, one-way links only from
+        // their source
+        let links: Vec<(Star, Connection)> = star_map
+            .stars()
+            .iter()
+            .flat_map(|star| star_map.connections(star.id).map(|c| (*star, c)))
+            .collect();
+        let one_way = (0..199).step_by(3).filter(|i| i % 4 == 0).count();
+        assert_eq!(links.len(), 2 * 67 - one_way);
+        for (star, conn) in links {
+            let target = star_map[&conn.target];
+            let i = star.id.min(target.id) as u32;
+            assert_eq!(conn.jump_type, (i / 3 % 3) as u8);
+            let expected = match (i, conn.conn_type.two_way()) {
+                (_, false) => ConnType::OneWay,
+                (i, _) if i / 3 % 3 == 2 => ConnType::Special,
+                (i, _) if i % 50 == 49 => ConnType::RegionGate,
+                (i, _) if i % 10 == 9 => ConnType::ConstellationGate,
+                _ => ConnType::Gate,
+            };
+            assert_eq!(conn.conn_type, expected);
+            assert_eq!(
+                star_map
+                    .connections(conn.target)
+                    .any(|back| back.target == star.id),
+                conn.conn_type.two_way()
+            );
+        }
+    }
+
+    #[test]
+    fn test_hierarchy() {
+        let star_map = star_map();
+        assert_eq!(star_map[&12].constellation(), Some(20000001));
+        assert_eq!(star_map[&12].region(), Some(10000000));
+        assert_eq!(star_map.constellation(20000001).len(), 10);
+        assert_eq!(star_map.region(10000003).len(), 50);
+    }
+
+    #[test]
+    fn test_find_by_name() {
+        // Stars are found by id or name
+        let star_map = star_map();
+        assert_eq!(star_map.name(12), Some("System-12"));
+        for system in ["30000012", "System-12", "system-12 "] {
+            let system = system.parse().unwrap();
+            assert_eq!(star_map.find(&system).unwrap().id, 12);
+        }
+        let err = star_map
+            .find(&SystemRef::Name("Sytsem-12".to_string()))
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("did you mean System-12"),
+            "{}",
+            err
+        );
+        assert!(star_map.find(&SystemRef::Id(30000200)).is_err());
+        // Out of the known ranges, even though it truncates to 12
+        assert!(star_map.find(&SystemRef::Id(12 + 474 * 65536)).is_err());
+        let system: SystemRef = serde_json::from_str(r#""30000012""#).unwrap();
+        assert_eq!(system, SystemRef::Id(30000012));
+    }
+}
@@ -1,13 +1,24 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::mem::size_of;
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use memmap2::Mmap;
 
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
 use uom::si::f64::*;
-use uom::si::length::meter;
+use uom::si::length::{astronomical_unit, kilometer, light_year, meter};
+use uom::si::mass::kilogram;
 
 use log::info;
 
+use super::astar::SearchLimits;
+use super::spatial::{self, SpatialIndex};
+use super::tools;
+
 pub type ConnectionId = u32;
 pub type SolarSystemId = u16;
 
@@ -17,21 +28,467 @@ pub enum PathOptimize {
     Fuel,
     Distance,
     Hops,
+    /// Combine jump distance, gates and hops with the request weights
+    Weighted,
+}
+
+/// Weights of the `weighted` optimization mode, the cost of a connection
+/// is the hop weight plus the weight of its kind. The `fuel` mode also
+/// multiplies the cost of connections other than jumps by the weight of
+/// their kind.
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+pub struct CostWeights {
+    /// Cost per light-year jumped
+    pub jump: f64,
+    /// Cost of taking a gate
+    pub gate: f64,
+    /// Cost of taking a gate between constellations, the gate weight when
+    /// not provided
+    #[serde(default)]
+    pub constellation_gate: Option<f64>,
+    /// Cost of taking a gate between regions, the gate weight when not
+    /// provided
+    #[serde(default)]
+    pub region_gate: Option<f64>,
+    /// Cost of taking a one-way link, the gate weight when not provided
+    #[serde(default)]
+    pub one_way: Option<f64>,
+    /// Cost of taking a smart gate
+    pub smart_gate: f64,
+    /// Cost of taking a special link, 1 when not provided
+    #[serde(default = "default_weight")]
+    pub special: f64,
+    /// Cost added to every connection
+    pub hop: f64,
+}
+
+impl Default for CostWeights {
+    fn default() -> Self {
+        CostWeights {
+            jump: 1.0,
+            gate: 1.0,
+            constellation_gate: None,
+            region_gate: None,
+            one_way: None,
+            smart_gate: 1.0,
+            special: 1.0,
+            hop: 0.0,
+        }
+    }
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+impl CostWeights {
+    /// Weight of a connection kind, per light-year for jumps
+    pub fn kind(&self, conn_type: &ConnType) -> f64 {
+        match conn_type {
+            ConnType::Jump => self.jump,
+            ConnType::Gate => self.gate,
+            ConnType::ConstellationGate => self.constellation_gate.unwrap_or(self.gate),
+            ConnType::RegionGate => self.region_gate.unwrap_or(self.gate),
+            ConnType::OneWay => self.one_way.unwrap_or(self.gate),
+            ConnType::Special => self.special,
+            ConnType::SmartGate => self.smart_gate,
+        }
+    }
+
+    /// Lowest weight of the connection kinds other than jumps
+    pub fn min_link(&self) -> f64 {
+        [
+            ConnType::Gate,
+            ConnType::ConstellationGate,
+            ConnType::RegionGate,
+            ConnType::OneWay,
+            ConnType::Special,
+            ConnType::SmartGate,
+        ]
+        .iter()
+        .map(|conn_type| self.kind(conn_type))
+        .fold(f64::INFINITY, f64::min)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, weight) in [
+            ("jump", self.jump),
+            ("gate", self.gate),
+            (
+                "constellation_gate",
+                self.kind(&ConnType::ConstellationGate),
+            ),
+            ("region_gate", self.kind(&ConnType::RegionGate)),
+            ("one_way", self.kind(&ConnType::OneWay)),
+            ("smart_gate", self.smart_gate),
+            ("special", self.special),
+            ("hop", self.hop),
+        ] {
+            if !weight.is_finite() || weight < 0.0 {
+                anyhow::bail!(
+                    "The {} weight must be zero or positive, got {}",
+                    name,
+                    weight
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(
+    clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PathAlgorithm {
+    /// Forward A* from the start system
+    #[default]
+    Astar,
+    /// A* from both ends at once, meeting in the middle
+    Bidirectional,
+}
+
+/// Unit of the distances in results. Searches always work in light-years,
+/// distances are only converted when results are built.
+#[derive(
+    clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema,
+)]
+pub enum DistanceUnit {
+    #[default]
+    #[serde(rename = "ly")]
+    #[value(name = "ly")]
+    LightYear,
+    #[serde(rename = "au")]
+    #[value(name = "au")]
+    AstronomicalUnit,
+    #[serde(rename = "m")]
+    #[value(name = "m")]
+    Meter,
+    #[serde(rename = "km")]
+    #[value(name = "km")]
+    Kilometer,
+}
+
+impl DistanceUnit {
+    pub fn get(&self, length: Length) -> f64 {
+        match self {
+            DistanceUnit::LightYear => length.get::<light_year>(),
+            DistanceUnit::AstronomicalUnit => length.get::<astronomical_unit>(),
+            DistanceUnit::Meter => length.get::<meter>(),
+            DistanceUnit::Kilometer => length.get::<kilometer>(),
+        }
+    }
+
+    /// Convert a distance in light-years, as in connections
+    pub fn convert_light_years(&self, distance: f64) -> f64 {
+        match self {
+            // Avoid a round trip through meters
+            DistanceUnit::LightYear => distance,
+            _ => self.get(Length::new::<light_year>(distance)),
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            DistanceUnit::LightYear => "ly",
+            DistanceUnit::AstronomicalUnit => "au",
+            DistanceUnit::Meter => "m",
+            DistanceUnit::Kilometer => "km",
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FuelType {
+    D1,
+    D2,
+    Sof40,
+    Sof80,
+    Eu40,
+    Eu90,
+}
+
+impl FuelType {
+    /// Share of the fuel turned into jump distance
+    pub fn efficiency(&self) -> f64 {
+        match self {
+            FuelType::D1 => 0.10,
+            FuelType::D2 => 0.15,
+            FuelType::Sof40 => 0.40,
+            FuelType::Sof80 => 0.80,
+            FuelType::Eu40 => 0.40,
+            FuelType::Eu90 => 0.90,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, ToSchema)]
+/// Fuel units burnt per kilogram of ship, per light-year, at 100% efficiency
+pub const FUEL_CONSTANT: f64 = 0.0000001;
+
+/// Ship description as provided by a request
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct Ship {
+    /// Ship mass in kilograms
+    pub mass: f64,
+    /// Cargo mass in kilograms
+    pub cargo_mass: Option<f64>,
+    pub fuel_type: Option<FuelType>,
+    /// Fuel efficiency between 0 and 1, takes precedence over fuel_type
+    pub fuel_efficiency: Option<f64>,
+}
+
+impl Ship {
+    pub fn fuel_model(&self) -> anyhow::Result<ShipFuel> {
+        let efficiency = match (self.fuel_efficiency, self.fuel_type) {
+            (Some(efficiency), _) => efficiency,
+            (None, Some(fuel_type)) => fuel_type.efficiency(),
+            (None, None) => anyhow::bail!("Either fuel_type or fuel_efficiency is required"),
+        };
+        if efficiency.is_nan() || efficiency <= 0.0 || efficiency > 1.0 {
+            anyhow::bail!(
+                "Fuel efficiency must be between 0 and 1, got {}",
+                efficiency
+            );
+        }
+        let total = self.mass + self.cargo_mass.unwrap_or(0.0);
+        if total.is_nan() || total <= 0.0 {
+            anyhow::bail!("Ship mass must be positive, got {} kg", total);
+        }
+        Ok(ShipFuel {
+            mass: Mass::new::<kilogram>(total),
+            efficiency,
+        })
+    }
+}
+
+/// Fuel consumption of a loaded ship: jumps burn fuel proportionally to
+/// the total mass and the distance, gates and other links are free
+#[derive(Debug, Clone, Copy)]
+pub struct ShipFuel {
+    /// Ship and cargo mass
+    pub mass: Mass,
+    pub efficiency: f64,
+}
+
+impl ShipFuel {
+    /// Fuel units burnt by a jump of the given distance
+    pub fn jump_fuel(&self, distance: Length) -> f64 {
+        FUEL_CONSTANT * self.mass.get::<kilogram>() * distance.get::<light_year>() / self.efficiency
+    }
+
+    /// Fuel units burnt travelling through a connection
+    pub fn connection_fuel(&self, conn: &Connection) -> f64 {
+        match conn.conn_type {
+            ConnType::Jump => self.jump_fuel(Length::new::<light_year>(conn.distance)),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Fuel tank as provided by a request. Fuel is counted in fuel units when
+/// a ship is provided, and in light-years of jumps otherwise.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct FuelTank {
+    pub capacity: f64,
+    /// Fuel at departure, defaults to a full tank
+    pub fuel: Option<f64>,
+    /// Systems where the tank can be refilled
+    pub refuel_systems: Vec<u32>,
+}
+
+impl FuelTank {
+    pub fn tank(&self) -> anyhow::Result<Tank> {
+        if self.capacity.is_nan() || self.capacity <= 0.0 {
+            anyhow::bail!("Tank capacity must be positive, got {}", self.capacity);
+        }
+        let fuel = self.fuel.unwrap_or(self.capacity);
+        if fuel.is_nan() || fuel < 0.0 {
+            anyhow::bail!("Fuel in the tank cannot be negative, got {}", fuel);
+        }
+        let refuel_systems = self
+            .refuel_systems
+            .iter()
+            .map(|id| {
+                tools::stored_system_id(*id)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid refuel system {}", id))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Tank {
+            capacity: self.capacity,
+            fuel: fuel.min(self.capacity),
+            refuel_systems,
+        })
+    }
+}
+
+/// Fuel tank limiting how far a ship can go between refuels
+#[derive(Debug, Clone)]
+pub struct Tank {
+    pub capacity: f64,
+    /// Fuel at departure
+    pub fuel: f64,
+    pub refuel_systems: HashSet<SolarSystemId>,
+}
+
+/// Systems and connections a search must never go through
+#[derive(Debug, Clone, Default)]
+pub struct Avoid {
+    pub systems: HashSet<SolarSystemId>,
+    pub connections: HashSet<ConnectionId>,
+}
+
+impl Avoid {
+    pub fn from_system_ids(systems: &[u32], connections: &[ConnectionId]) -> anyhow::Result<Self> {
+        Ok(Avoid {
+            systems: systems
+                .iter()
+                .map(|&id| {
+                    tools::stored_system_id(id)
+                        .ok_or_else(|| anyhow::anyhow!("Invalid system id {} to avoid", id))
+                })
+                .collect::<anyhow::Result<_>>()?,
+            connections: connections.iter().copied().collect(),
+        })
+    }
+
+    /// Also avoid every system of these regions
+    pub fn with_regions(mut self, star_map: &StarMap, regions: &[u32]) -> Self {
+        for region in regions {
+            self.systems
+                .extend(star_map.region(*region).into_iter().map(|star| star.id));
+        }
+        self
+    }
+
+    pub fn contains(&self, conn: &Connection) -> bool {
+        self.systems.contains(&conn.target) || self.connections.contains(&conn.id)
+    }
+}
+
+/// Search settings shared by every path calculation
+#[derive(Debug, Clone)]
+pub struct PathOptions {
+    pub jump_distance: u16,
+    pub optimize: PathOptimize,
+    /// Used by the weighted optimization mode, and for the links of the fuel
+    /// mode
+    pub weights: CostWeights,
+    pub algorithm: PathAlgorithm,
+    pub avoid: Avoid,
+    /// When set, fuel is reported and optimized with this model instead of
+    /// the jump distance
+    pub ship: Option<ShipFuel>,
+    /// When set, only routes the tank can sustain are considered, refuelling
+    /// where possible
+    pub tank: Option<Tank>,
+    pub limits: SearchLimits,
+    /// Unit of the distances in the results
+    pub unit: DistanceUnit,
+}
+
+impl PathOptions {
+    /// Reject what a fuel tank cannot be searched with: the fuel left is
+    /// only known going forward, along the best route
+    pub fn validate(&self, alternatives: usize) -> anyhow::Result<()> {
+        if self.tank.is_some() && self.algorithm == PathAlgorithm::Bidirectional {
+            anyhow::bail!("A fuel tank cannot be used with the bidirectional algorithm");
+        }
+        if self.tank.is_some() && alternatives > 0 {
+            anyhow::bail!("A fuel tank cannot be used with alternative routes");
+        }
+        Ok(())
+    }
+}
+
+#[derive(
+    clap::ValueEnum,
+    Debug,
+    Deserialize,
+    Serialize,
+    Clone,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    ToSchema,
+)]
 #[serde(rename_all = "lowercase")]
+#[value(rename_all = "lower")]
 pub enum ConnType {
+    /// Stargate between two systems of the same constellation
     Gate,
+    /// Stargate between two constellations of the same region
+    ConstellationGate,
+    /// Stargate between two regions
+    RegionGate,
+    /// Link that can only be travelled from its source system
+    OneWay,
+    /// Any other link of the game data, such as temporary ones
+    Special,
     SmartGate,
     Jump,
 }
 
+impl ConnType {
+    /// Stargates of the game data, whatever the systems they link
+    pub fn is_stargate(&self) -> bool {
+        matches!(
+            self,
+            ConnType::Gate | ConnType::ConstellationGate | ConnType::RegionGate
+        )
+    }
+
+    /// Whether the connection can be travelled in both directions. Two-way
+    /// connections are stored on both of their systems, other ones only on
+    /// their source system.
+    pub fn two_way(&self) -> bool {
+        !matches!(self, ConnType::OneWay | ConnType::SmartGate)
+    }
+
+    /// Whether the connection was built from a jump of the game data, and
+    /// carries its jump type
+    pub fn is_game_link(&self) -> bool {
+        !matches!(self, ConnType::SmartGate | ConnType::Jump)
+    }
+
+    /// Kind of the stargate linking two systems, from their constellation
+    /// and region. Unknown ones are taken as the same.
+    pub fn stargate(from: &Star, to: &Star) -> Self {
+        let differ = |a: u32, b: u32| a != 0 && b != 0 && a != b;
+        if differ(from.region_id, to.region_id) {
+            ConnType::RegionGate
+        } else if differ(from.constellation_id, to.constellation_id) {
+            ConnType::ConstellationGate
+        } else {
+            ConnType::Gate
+        }
+    }
+
+    /// Same name as in requests and results
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConnType::Gate => "gate",
+            ConnType::ConstellationGate => "constellationgate",
+            ConnType::RegionGate => "regiongate",
+            ConnType::OneWay => "oneway",
+            ConnType::Special => "special",
+            ConnType::SmartGate => "smartgate",
+            ConnType::Jump => "jump",
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Connection {
     pub id: ConnectionId,
     pub conn_type: ConnType,
-    pub distance: u16,
+    /// Jump type of the game data the connection was built from, zero for
+    /// smart gates and jumps
+    pub jump_type: u8,
+    /// Light-years
+    pub distance: f64,
     pub target: SolarSystemId,
 }
 impl PartialEq for Connection {
@@ -54,20 +511,55 @@ impl Ord for Connection {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.conn_type
             .cmp(&other.conn_type)
-            .then_with(|| self.distance.partial_cmp(&other.distance).unwrap())
+            .then_with(|| self.distance.total_cmp(&other.distance))
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+/// A star, as stored in the star map file
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, Pod, Zeroable)]
 pub struct Star {
-    pub id: SolarSystemId,
     pub x: f64,
     pub y: f64,
     pub z: f64,
-    pub connections: Vec<Connection>,
+    /// Zero when unknown
+    pub constellation_id: u32,
+    /// Zero when unknown
+    pub region_id: u32,
+    pub id: SolarSystemId,
+    _padding: [u8; 6],
 }
 
 impl Star {
+    pub fn new(id: SolarSystemId, x: f64, y: f64, z: f64) -> Self {
+        Star {
+            x,
+            y,
+            z,
+            constellation_id: 0,
+            region_id: 0,
+            id,
+            _padding: [0; 6],
+        }
+    }
+
+    /// The same star, in a constellation and a region
+    pub fn with_hierarchy(self, constellation_id: u32, region_id: u32) -> Self {
+        Star {
+            constellation_id,
+            region_id,
+            ..self
+        }
+    }
+
+    pub fn constellation(&self) -> Option<u32> {
+        Some(self.constellation_id).filter(|id| *id != 0)
+    }
+
+    pub fn region(&self) -> Option<u32> {
+        Some(self.region_id).filter(|id| *id != 0)
+    }
+
     pub fn distance(&self, other: &Star) -> Length {
         Length::new::<meter>(
             ((self.x - other.x).powi(2) + (self.y - other.y).powi(2) + (self.z - other.z).powi(2))
@@ -87,27 +579,820 @@ impl std::hash::Hash for Star {
     }
 }
 
-pub fn save_star_map(star_map: &HashMap<SolarSystemId, Star>, dest: &str) -> anyhow::Result<()> {
-    info!(
-        "Saving star map to binary {}",
-        bincode::serialized_size(&star_map)?
-    );
-    let file = std::fs::File::create(dest)?;
-    bincode::serialize_into(file, &star_map)?;
-    Ok(())
+/// A connection, as stored in the star map file
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct ConnectionRecord {
+    /// Light-years
+    distance: f64,
+    id: ConnectionId,
+    target: SolarSystemId,
+    conn_type: u8,
+    jump_type: u8,
+}
+
+impl ConnType {
+    fn to_u8(&self) -> u8 {
+        match self {
+            ConnType::Gate => 0,
+            ConnType::SmartGate => 1,
+            ConnType::Jump => 2,
+            ConnType::ConstellationGate => 3,
+            ConnType::RegionGate => 4,
+            ConnType::OneWay => 5,
+            ConnType::Special => 6,
+        }
+    }
+
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ConnType::Gate),
+            1 => Some(ConnType::SmartGate),
+            2 => Some(ConnType::Jump),
+            3 => Some(ConnType::ConstellationGate),
+            4 => Some(ConnType::RegionGate),
+            5 => Some(ConnType::OneWay),
+            6 => Some(ConnType::Special),
+            _ => None,
+        }
+    }
+}
+
+impl From<&Connection> for ConnectionRecord {
+    fn from(conn: &Connection) -> Self {
+        ConnectionRecord {
+            id: conn.id,
+            distance: conn.distance,
+            target: conn.target,
+            conn_type: conn.conn_type.to_u8(),
+            jump_type: conn.jump_type,
+        }
+    }
+}
+
+impl From<&ConnectionRecord> for Connection {
+    fn from(record: &ConnectionRecord) -> Self {
+        Connection {
+            id: record.id,
+            // Checked when the star map is loaded
+            conn_type: ConnType::from_u8(record.conn_type).unwrap(),
+            jump_type: record.jump_type,
+            distance: record.distance,
+            target: record.target,
+        }
+    }
+}
+
+/// Identifies star map files
+const MAGIC: [u8; 8] = *b"EDCSTARS";
+
+/// Bumped whenever the layout or the meaning of the file changes
+pub const FORMAT_VERSION: u32 = 6;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct Header {
+    magic: [u8; 8],
+    format_version: u32,
+    star_count: u32,
+    connection_count: u32,
+    source_checksum: u32,
+    source_size: u64,
+    /// CRC32 of everything after the header
+    checksum: u32,
+    names_size: u32,
+    /// Version of the code that built the file, zero padded
+    code_version: [u8; 16],
+    /// Patches applied since the build
+    patches: u32,
+    /// Checksums of the patches applied since the build, combined in order
+    patch_checksum: u32,
+}
+
+impl Header {
+    fn read(bytes: &[u8]) -> anyhow::Result<Self> {
+        let header: Header = bytes
+            .get(..size_of::<Header>())
+            .map(bytemuck::pod_read_unaligned)
+            .ok_or_else(|| anyhow::anyhow!("Star map file is too short"))?;
+        if header.magic != MAGIC {
+            anyhow::bail!("Not a star map file, or built by an older version: rebuild it");
+        }
+        if header.format_version != FORMAT_VERSION {
+            anyhow::bail!(
+                "Star map file format version {} is not supported, expected {}: rebuild it",
+                header.format_version,
+                FORMAT_VERSION
+            );
+        }
+        Ok(header)
+    }
+
+    fn info(&self) -> StarMapInfo {
+        let code_version = self
+            .code_version
+            .split(|b| *b == 0)
+            .next()
+            .unwrap_or_default();
+        StarMapInfo {
+            format_version: self.format_version,
+            code_version: String::from_utf8_lossy(code_version).into_owned(),
+            source_size: self.source_size,
+            source_checksum: format!("{:08x}", self.source_checksum),
+            patches: self.patches,
+            patch_checksum: format!("{:08x}", self.patch_checksum),
+            stars: self.star_count,
+            connections: self.connection_count,
+            checksum: format!("{:08x}", self.checksum),
+        }
+    }
+}
+
+/// The source data a star map is built from, and the patches applied
+/// since, recorded in its header
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BuildSource {
+    pub size: u64,
+    pub checksum: u32,
+    pub patches: u32,
+    pub patch_checksum: u32,
 }
 
-pub type StarMap = HashMap<SolarSystemId, Star>;
+impl BuildSource {
+    /// Size and checksum of the files, as if they were one
+    pub fn from_files(paths: &[std::path::PathBuf]) -> anyhow::Result<Self> {
+        let mut hasher = crc32fast::Hasher::new();
+        let mut size = 0;
+        let mut buffer = vec![0; 1 << 16];
+        for path in paths {
+            let mut file = std::fs::File::open(path)?;
+            loop {
+                let read = file.read(&mut buffer)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+                size += read as u64;
+            }
+        }
+        Ok(BuildSource {
+            size,
+            checksum: hasher.finalize(),
+            ..Default::default()
+        })
+    }
+
+    /// The same source, with one more patch of the given checksum applied
+    pub fn patched(self, checksum: u32) -> Self {
+        let mut hasher = crc32fast::Hasher::new_with_initial(self.patch_checksum);
+        hasher.update(&checksum.to_le_bytes());
+        BuildSource {
+            patches: self.patches + 1,
+            patch_checksum: hasher.finalize(),
+            ..self
+        }
+    }
+}
+
+/// What a star map header tells
+#[derive(Debug, Serialize)]
+pub struct StarMapInfo {
+    pub format_version: u32,
+    pub code_version: String,
+    pub source_size: u64,
+    pub source_checksum: String,
+    /// Patches applied since the build, zero when built as is
+    pub patches: u32,
+    pub patch_checksum: String,
+    pub stars: u32,
+    pub connections: u32,
+    pub checksum: String,
+}
+
+/// Byte ranges of the star map file sections, one after the other:
+/// - the header
+/// - the stars with their constellation and region, arranged as a k-d tree
+///   (see `SpatialIndex`)
+/// - the connections, star after star
+/// - the positions of the stars in the first section, in id order
+/// - where the connections of each star start in the second section, and
+///   where the connections of the last star end
+/// - where the name of each star starts in the last section, and where the
+///   name of the last star ends
+/// - the positions of the stars, in lowercase name order
+/// - the names, star after star, in UTF-8
+///
+/// Every section is aligned for its type, so the file can be used as is.
+#[derive(Debug, Clone)]
+struct Layout {
+    stars: Range<usize>,
+    connections: Range<usize>,
+    by_id: Range<usize>,
+    offsets: Range<usize>,
+    name_offsets: Range<usize>,
+    by_name: Range<usize>,
+    names: Range<usize>,
+}
+
+impl Layout {
+    fn new(star_count: usize, connection_count: usize, names_size: usize) -> Self {
+        let header = size_of::<Header>();
+        let stars = header..header + star_count * size_of::<Star>();
+        let connections = stars.end..stars.end + connection_count * size_of::<ConnectionRecord>();
+        let by_id = connections.end..connections.end + star_count * size_of::<u32>();
+        let offsets = by_id.end..by_id.end + (star_count + 1) * size_of::<u32>();
+        let name_offsets = offsets.end..offsets.end + (star_count + 1) * size_of::<u32>();
+        let by_name = name_offsets.end..name_offsets.end + star_count * size_of::<u32>();
+        let names = by_name.end..by_name.end + names_size;
+        Layout {
+            stars,
+            connections,
+            by_id,
+            offsets,
+            name_offsets,
+            by_name,
+            names,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.names.end
+    }
+}
+
+#[derive(Debug)]
+enum Storage {
+    Mapped(Mmap),
+    /// u64 to keep every section aligned
+    Owned(Vec<u64>),
+}
+
+impl Storage {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Storage::Mapped(mmap) => mmap,
+            Storage::Owned(words) => bytemuck::cast_slice(words),
+        }
+    }
+}
+
+/// A star and what the star map keeps about it
+#[derive(Debug, Clone, Default)]
+pub struct StarEntry {
+    pub star: Star,
+    /// Empty when unknown
+    pub name: String,
+    pub connections: Vec<Connection>,
+}
+
+/// Stars with their names and gates, in a compressed sparse row layout that is read
+/// straight from the memory-mapped file, and a spatial index to find the
+/// jumps between them. Jumps are not stored: they are computed when a
+/// search asks for them, so any jump distance can be used.
+#[derive(Debug)]
+pub struct StarMap {
+    storage: Storage,
+    layout: Layout,
+    /// One-way links reversed, keyed by the system they lead to, built once
+    /// for the reverse searches
+    incoming: SmartGatesMap,
+}
+
+/// Jump ids start after this, gates and smart gates are numbered below it
+const JUMP_ID_START: ConnectionId = 1 << 31;
+
+impl StarMap {
+    /// Build a star map from stars, their names and their connections.
+    /// Jumps are dropped, they are computed on the fly.
+    pub fn new(entries: Vec<StarEntry>, source: &BuildSource) -> anyhow::Result<Self> {
+        // Every ordered pair of stars needs its own jump id
+        if entries.len().pow(2) > (ConnectionId::MAX - JUMP_ID_START) as usize {
+            anyhow::bail!("Too many stars to number the jumps: {}", entries.len());
+        }
+        let mut arranged: Vec<Star> = entries.iter().map(|entry| entry.star).collect();
+        spatial::arrange(&mut arranged);
+        let mut entries: HashMap<SolarSystemId, StarEntry> = entries
+            .into_iter()
+            .map(|entry| (entry.star.id, entry))
+            .collect();
+        if entries.len() != arranged.len() {
+            anyhow::bail!("Duplicate stars in the star map");
+        }
+
+        let mut by_id: Vec<u32> = (0..arranged.len() as u32).collect();
+        by_id.sort_unstable_by_key(|&i| arranged[i as usize].id);
+        let mut offsets: Vec<u32> = vec![0];
+        let mut records: Vec<ConnectionRecord> = vec![];
+        let mut name_offsets: Vec<u32> = vec![0];
+        let mut names = String::new();
+        for star in &arranged {
+            let entry = entries.remove(&star.id).unwrap();
+            records.extend(
+                entry
+                    .connections
+                    .iter()
+                    .filter(|c| c.conn_type != ConnType::Jump)
+                    .map(ConnectionRecord::from),
+            );
+            offsets.push(records.len() as u32);
+            names.push_str(&entry.name);
+            name_offsets.push(names.len() as u32);
+        }
+        let mut by_name: Vec<u32> = (0..arranged.len() as u32).collect();
+        by_name.sort_by_cached_key(|&i| {
+            let i = i as usize;
+            let name = &names[name_offsets[i] as usize..name_offsets[i + 1] as usize];
+            (name.to_lowercase(), arranged[i].id)
+        });
+
+        let layout = Layout::new(arranged.len(), records.len(), names.len());
+        let mut words = vec![0u64; layout.len().div_ceil(size_of::<u64>())];
+        let bytes: &mut [u8] = bytemuck::cast_slice_mut(&mut words);
+        bytes[layout.stars.clone()].copy_from_slice(bytemuck::cast_slice(&arranged));
+        bytes[layout.by_id.clone()].copy_from_slice(bytemuck::cast_slice(&by_id));
+        bytes[layout.offsets.clone()].copy_from_slice(bytemuck::cast_slice(&offsets));
+        bytes[layout.connections.clone()].copy_from_slice(bytemuck::cast_slice(&records));
+        bytes[layout.name_offsets.clone()].copy_from_slice(bytemuck::cast_slice(&name_offsets));
+        bytes[layout.by_name.clone()].copy_from_slice(bytemuck::cast_slice(&by_name));
+        bytes[layout.names.clone()].copy_from_slice(names.as_bytes());
+
+        let mut code_version = [0u8; 16];
+        let version = env!("CARGO_PKG_VERSION").as_bytes();
+        let length = version.len().min(code_version.len());
+        code_version[..length].copy_from_slice(&version[..length]);
+        let header = Header {
+            magic: MAGIC,
+            format_version: FORMAT_VERSION,
+            star_count: arranged.len() as u32,
+            connection_count: records.len() as u32,
+            source_checksum: source.checksum,
+            source_size: source.size,
+            checksum: crc32fast::hash(&bytes[size_of::<Header>()..layout.len()]),
+            names_size: names.len() as u32,
+            code_version,
+            patches: source.patches,
+            patch_checksum: source.patch_checksum,
+        };
+        bytes[..size_of::<Header>()].copy_from_slice(bytemuck::bytes_of(&header));
+        Self::from_storage(Storage::Owned(words))
+    }
+
+    /// Map a star map file in memory. Nothing is copied, the pages are read
+    /// when the searches need them.
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // The file must not change while it is mapped
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::from_storage(Storage::Mapped(mmap))
+    }
+
+    fn from_storage(storage: Storage) -> anyhow::Result<Self> {
+        let bytes = storage.bytes();
+        let header = Header::read(bytes)?;
+        let layout = Layout::new(
+            header.star_count as usize,
+            header.connection_count as usize,
+            header.names_size as usize,
+        );
+        if bytes.len() < layout.len() {
+            anyhow::bail!(
+                "Star map file is truncated: {} bytes instead of {}",
+                bytes.len(),
+                layout.len()
+            );
+        }
+        let checksum = crc32fast::hash(&bytes[size_of::<Header>()..layout.len()]);
+        if checksum != header.checksum {
+            anyhow::bail!(
+                "Star map file is corrupted: checksum {:08x} instead of {:08x}",
+                checksum,
+                header.checksum
+            );
+        }
+        let mut star_map = StarMap {
+            storage,
+            layout,
+            incoming: HashMap::new(),
+        };
+        star_map.check()?;
+        let one_way: Vec<(SolarSystemId, Connection)> = star_map
+            .stars()
+            .iter()
+            .flat_map(|star| {
+                star_map
+                    .connections(star.id)
+                    .filter(|c| !c.conn_type.two_way())
+                    .map(move |c| (star.id, c))
+            })
+            .collect();
+        star_map.incoming = reverse(one_way);
+        Ok(star_map)
+    }
+
+    /// Make sure the accessors cannot fail on a corrupted file
+    fn check(&self) -> anyhow::Result<()> {
+        let offsets = self.offsets();
+        if offsets.first() != Some(&0)
+            || offsets.windows(2).any(|pair| pair[0] > pair[1])
+            || offsets.last().copied() != Some(self.records().len() as u32)
+        {
+            anyhow::bail!("Star map file has invalid connection offsets");
+        }
+        let by_id = self.by_id();
+        let stars = self.stars();
+        if by_id.iter().any(|&i| i as usize >= stars.len())
+            || by_id
+                .windows(2)
+                .any(|pair| stars[pair[0] as usize].id >= stars[pair[1] as usize].id)
+        {
+            anyhow::bail!("Star map file has an invalid star index");
+        }
+        for record in self.records() {
+            if ConnType::from_u8(record.conn_type).is_none() {
+                anyhow::bail!("Star map file has an unknown connection type");
+            }
+            if self.position(record.target).is_none() {
+                anyhow::bail!("Star map file has a connection to an unknown star");
+            }
+        }
+        let name_offsets = self.name_offsets();
+        if name_offsets.first() != Some(&0)
+            || name_offsets.windows(2).any(|pair| pair[0] > pair[1])
+            || name_offsets.last().copied() != Some(self.names().len() as u32)
+            || name_offsets.windows(2).any(|pair| {
+                std::str::from_utf8(&self.names()[pair[0] as usize..pair[1] as usize]).is_err()
+            })
+        {
+            anyhow::bail!("Star map file has invalid names");
+        }
+        if self.by_name().iter().any(|&i| i as usize >= stars.len()) {
+            anyhow::bail!("Star map file has an invalid name index");
+        }
+        Ok(())
+    }
+
+    pub fn info(&self) -> StarMapInfo {
+        // Checked when the star map is loaded
+        Header::read(self.storage.bytes()).unwrap().info()
+    }
+
+    /// The source data the star map was built from
+    pub fn source(&self) -> BuildSource {
+        // Checked when the star map is loaded
+        let header = Header::read(self.storage.bytes()).unwrap();
+        BuildSource {
+            size: header.source_size,
+            checksum: header.source_checksum,
+            patches: header.patches,
+            patch_checksum: header.patch_checksum,
+        }
+    }
+
+    /// Every star with its name and connections, by id: what `new` needs
+    /// to build the star map again
+    pub fn entries(&self) -> Vec<StarEntry> {
+        let stars = self.stars();
+        self.by_id()
+            .iter()
+            .map(|&i| {
+                let star = stars[i as usize];
+                StarEntry {
+                    star,
+                    name: self.name_at(i as usize).to_string(),
+                    connections: self.connections(star.id).collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// The file content
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.storage.bytes()[..self.layout.len()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.stars().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stars().is_empty()
+    }
+
+    /// Every star, in the order of the spatial index
+    pub fn stars(&self) -> &[Star] {
+        bytemuck::cast_slice(&self.storage.bytes()[self.layout.stars.clone()])
+    }
+
+    fn by_id(&self) -> &[u32] {
+        bytemuck::cast_slice(&self.storage.bytes()[self.layout.by_id.clone()])
+    }
+
+    fn offsets(&self) -> &[u32] {
+        bytemuck::cast_slice(&self.storage.bytes()[self.layout.offsets.clone()])
+    }
+
+    fn records(&self) -> &[ConnectionRecord] {
+        bytemuck::cast_slice(&self.storage.bytes()[self.layout.connections.clone()])
+    }
+
+    fn name_offsets(&self) -> &[u32] {
+        bytemuck::cast_slice(&self.storage.bytes()[self.layout.name_offsets.clone()])
+    }
+
+    fn by_name(&self) -> &[u32] {
+        bytemuck::cast_slice(&self.storage.bytes()[self.layout.by_name.clone()])
+    }
+
+    fn names(&self) -> &[u8] {
+        &self.storage.bytes()[self.layout.names.clone()]
+    }
+
+    /// Name of the star at a position in `stars`, empty when unknown
+    fn name_at(&self, i: usize) -> &str {
+        let name_offsets = self.name_offsets();
+        let bytes = &self.names()[name_offsets[i] as usize..name_offsets[i + 1] as usize];
+        // Checked when the star map is loaded
+        std::str::from_utf8(bytes).unwrap()
+    }
+
+    /// Position of a star in `stars`
+    fn position(&self, id: SolarSystemId) -> Option<usize> {
+        let stars = self.stars();
+        let by_id = self.by_id();
+        by_id
+            .binary_search_by_key(&id, |&i| stars[i as usize].id)
+            .ok()
+            .map(|i| by_id[i] as usize)
+    }
+
+    pub fn get(&self, id: &SolarSystemId) -> Option<&Star> {
+        self.position(*id).map(|i| &self.stars()[i])
+    }
+
+    pub fn contains_key(&self, id: &SolarSystemId) -> bool {
+        self.position(*id).is_some()
+    }
+
+    pub fn name(&self, id: SolarSystemId) -> Option<&str> {
+        self.position(id)
+            .map(|i| self.name_at(i))
+            .filter(|name| !name.is_empty())
+    }
+
+    /// A star from its id, or from its name whatever the case. The error
+    /// suggests the closest names when there is no such star.
+    pub fn find(&self, system: &SystemRef) -> anyhow::Result<&Star> {
+        let name = match system {
+            SystemRef::Id(id) => {
+                return tools::stored_system_id(*id)
+                    .and_then(|id| self.get(&id))
+                    .ok_or_else(|| anyhow::anyhow!("Unknown system {}", id))
+            }
+            SystemRef::Name(name) => name.trim().to_lowercase(),
+        };
+        let by_name = self.by_name();
+        let first = by_name.partition_point(|&i| self.name_at(i as usize).to_lowercase() < name);
+        if let Some(&i) = by_name.get(first) {
+            if !name.is_empty() && self.name_at(i as usize).to_lowercase() == name {
+                return Ok(&self.stars()[i as usize]);
+            }
+        }
+
+        let mut suggestions: Vec<(f64, &str)> = by_name
+            .iter()
+            .map(|&i| self.name_at(i as usize))
+            .filter(|candidate| !candidate.is_empty())
+            .map(|candidate| {
+                let score = strsim::jaro_winkler(&name, &candidate.to_lowercase());
+                (score, candidate)
+            })
+            .filter(|(score, _)| *score >= SUGGESTION_SCORE)
+            .collect();
+        suggestions.sort_by(|a, b| b.0.total_cmp(&a.0));
+        suggestions.dedup_by_key(|(_, candidate)| *candidate);
+        let suggestions: Vec<&str> = suggestions
+            .into_iter()
+            .take(SUGGESTIONS)
+            .map(|(_, candidate)| candidate)
+            .collect();
+        if suggestions.is_empty() {
+            anyhow::bail!("Unknown system {}", system);
+        }
+        anyhow::bail!(
+            "Unknown system {}, did you mean {}?",
+            system,
+            suggestions.join(", ")
+        )
+    }
+
+    /// Stars of a constellation, by id
+    pub fn constellation(&self, constellation_id: u32) -> Vec<&Star> {
+        self.stars_where(|star| star.constellation() == Some(constellation_id))
+    }
+
+    /// Stars of a region, by id
+    pub fn region(&self, region_id: u32) -> Vec<&Star> {
+        self.stars_where(|star| star.region() == Some(region_id))
+    }
+
+    fn stars_where(&self, filter: impl Fn(&Star) -> bool) -> Vec<&Star> {
+        let stars = self.stars();
+        self.by_id()
+            .iter()
+            .map(|&i| &stars[i as usize])
+            .filter(|star| filter(star))
+            .collect()
+    }
+
+    /// Gates and smart gates stored for a star
+    pub fn connections(&self, id: SolarSystemId) -> impl Iterator<Item = Connection> + '_ {
+        let range = match self.position(id) {
+            Some(i) => self.offsets()[i] as usize..self.offsets()[i + 1] as usize,
+            None => 0..0,
+        };
+        self.records()[range].iter().map(Connection::from)
+    }
+
+    /// One-way links stored for other stars that lead to this one, pointing
+    /// back at their source
+    pub fn incoming(&self, id: SolarSystemId) -> &[Connection] {
+        self.incoming.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Jumps from a star to every other star at most `jump_distance` light
+    /// years away, shortest first
+    pub fn jumps(&self, star: &Star, jump_distance: u16) -> Vec<Connection> {
+        let Some(from) = self.position(star.id) else {
+            return vec![];
+        };
+        let stars = self.stars();
+        let radius = Length::new::<light_year>(jump_distance as f64 + 1.0);
+        let mut jumps: Vec<Connection> = SpatialIndex::new(stars)
+            .within(star, radius)
+            .into_iter()
+            .map(|(to, distance)| (to, distance.get::<light_year>()))
+            .filter(|(_, distance)| *distance > 0.0 && *distance <= jump_distance as f64)
+            .map(|(to, distance)| Connection {
+                id: self.jump_id(from, to),
+                conn_type: ConnType::Jump,
+                jump_type: 0,
+                distance,
+                target: stars[to].id,
+            })
+            .collect();
+        jumps.sort_unstable_by(|a, b| {
+            a.distance
+                .total_cmp(&b.distance)
+                .then(a.target.cmp(&b.target))
+        });
+        jumps
+    }
+
+    /// Source and target of a jump, from its id
+    pub fn jump_systems(&self, id: ConnectionId) -> Option<(SolarSystemId, SolarSystemId)> {
+        let offset = id.checked_sub(JUMP_ID_START)? as usize;
+        let stars = self.stars();
+        let count = stars.len();
+        if offset >= count * count {
+            return None;
+        }
+        Some((stars[offset / count].id, stars[offset % count].id))
+    }
+
+    /// Id of the jump going the other way
+    pub fn reverse_jump_id(&self, id: ConnectionId) -> Option<ConnectionId> {
+        let (from, to) = self.jump_systems(id)?;
+        Some(self.jump_id(self.position(to)?, self.position(from)?))
+    }
+
+    fn jump_id(&self, from: usize, to: usize) -> ConnectionId {
+        JUMP_ID_START + (from * self.len() + to) as ConnectionId
+    }
+}
+
+/// Names are suggested when they are at least this similar to the unknown
+/// one, on a Jaro-Winkler scale from 0 to 1
+const SUGGESTION_SCORE: f64 = 0.8;
+
+/// How many names are suggested at most
+const SUGGESTIONS: usize = 3;
+
+/// A solar system, from its id or its name. Ids may be given as strings.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(untagged, from = "SystemRefInput")]
+pub enum SystemRef {
+    Id(u32),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SystemRefInput {
+    Id(u32),
+    Name(String),
+}
+
+impl From<SystemRefInput> for SystemRef {
+    fn from(input: SystemRefInput) -> Self {
+        match input {
+            SystemRefInput::Id(id) => SystemRef::Id(id),
+            // Parsed as on the command line
+            SystemRefInput::Name(name) => {
+                let Ok(system) = name.parse();
+                system
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for SystemRef {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim().parse() {
+            Ok(id) => SystemRef::Id(id),
+            Err(_) => SystemRef::Name(s.to_string()),
+        })
+    }
+}
+
+impl std::fmt::Display for SystemRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SystemRef::Id(id) => write!(f, "{}", id),
+            SystemRef::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+impl std::ops::Index<&SolarSystemId> for StarMap {
+    type Output = Star;
+
+    fn index(&self, id: &SolarSystemId) -> &Star {
+        self.get(id).expect("Unknown star")
+    }
+}
 
 pub type SmartGatesMap = HashMap<SolarSystemId, Vec<Connection>>;
 
+/// Reverse the smart gates of a request, keyed by the system they lead to.
+/// The reversed connections point back at their source system.
+///
+/// Two-way connections and jumps are symmetric, so a star's own connection
+/// list already tells who can reach it; one-way links stored in the star map
+/// are reversed once, in `StarMap::incoming`.
+pub fn reverse_smart_gates(smart_gates_map: &SmartGatesMap) -> SmartGatesMap {
+    reverse(
+        smart_gates_map
+            .iter()
+            .flat_map(|(from, conns)| conns.iter().map(move |c| (*from, c.clone()))),
+    )
+}
+
+fn reverse(links: impl IntoIterator<Item = (SolarSystemId, Connection)>) -> SmartGatesMap {
+    let mut reverse: SmartGatesMap = HashMap::new();
+    for (from, conn) in links {
+        reverse.entry(conn.target).or_default().push(Connection {
+            id: conn.id,
+            conn_type: conn.conn_type,
+            jump_type: conn.jump_type,
+            distance: conn.distance,
+            target: from,
+        });
+    }
+    reverse
+}
+
+/// Write the star map next to `dest`, then move it there, so that a star
+/// map mapped from `dest` keeps its own pages
+pub fn save_star_map(star_map: &StarMap, dest: &str) -> anyhow::Result<()> {
+    info!("Saving star map to binary {}", star_map.as_bytes().len());
+    let tmp = format!("{}.tmp", dest);
+    std::fs::write(&tmp, star_map.as_bytes())?;
+    std::fs::rename(&tmp, dest)?;
+    Ok(())
+}
+
 pub fn get_star_map(path: &str) -> anyhow::Result<StarMap> {
-    let map: StarMap = bincode::deserialize(&std::fs::read(path)?)?;
-    Ok(map)
+    StarMap::open(path).map_err(|err| anyhow::anyhow!("Invalid star map {}: {}", path, err))
+}
+
+/// Read a star map header, without checking the rest of the file
+pub fn get_star_map_info(path: &str) -> anyhow::Result<StarMapInfo> {
+    use std::io::Read;
+    let mut bytes = Vec::new();
+    std::fs::File::open(path)?
+        .take(size_of::<Header>() as u64)
+        .read_to_end(&mut bytes)?;
+    Ok(Header::read(&bytes)?.info())
 }
 
 #[derive(serde::Serialize, Debug, ToSchema)]
 pub struct PathResultStats {
+    /// In hundredths: of a light-year with the `distance` mode and the
+    /// `fuel` mode without a ship (where a gate costs as much as a 1 ly
+    /// jump), of a fuel unit with a ship, and of a weight with the
+    /// `weighted` mode. A hop costs 100 with the `hops` mode.
     pub cost: i64,
     pub total_time: u128,
     pub successors_spend: u128,
@@ -117,13 +1402,27 @@ pub struct PathResultStats {
 
 #[derive(serde::Serialize, Debug, ToSchema)]
 pub struct PathResultConnection {
+    /// Stargates used to all be `gate`, they are now `gate`,
+    /// `constellationgate` or `regiongate` from what they link, and other
+    /// links of the game data are `oneway` or `special`
     pub conn_type: ConnType,
-    pub distance: u16,
+    /// Jump type of the game data, for connections built from it
+    pub jump_type: Option<u8>,
+    /// In the unit of the result
+    pub distance: f64,
     pub target: u32,
+    /// Name of the target system, when known
+    pub target_name: Option<String>,
+    /// Constellation of the target system, when known
+    pub target_constellation: Option<u32>,
+    /// Region of the target system, when known
+    pub target_region: Option<u32>,
     pub id: u32,
+    /// Fuel units burnt on this hop, when a ship was provided
+    pub fuel: Option<f64>,
 }
 
-#[derive(serde::Serialize, Debug, ToSchema)]
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum PathResultStatus {
     Found,
@@ -131,14 +1430,126 @@ pub enum PathResultStatus {
     Timeout,
 }
 
+#[derive(serde::Serialize, Debug, ToSchema)]
+pub struct PathAlternative {
+    /// Same unit as the stats cost
+    pub cost: i64,
+    /// Fuel units burnt on the route, when a ship was provided
+    pub fuel: Option<f64>,
+    pub path: Vec<PathResultConnection>,
+}
+
 #[derive(serde::Serialize, Debug, ToSchema)]
 pub struct PathResult {
     pub status: PathResultStatus,
     pub path: Vec<PathResultConnection>,
+    /// Fuel units burnt on the route, when a ship was provided
+    pub fuel: Option<f64>,
+    /// Systems where the tank must be refilled, when a tank was provided
+    pub refuels: Vec<u32>,
+    /// Fuel left in the tank on arrival, when a tank was provided
+    pub fuel_left: Option<f64>,
+    /// Regions the route goes through, in order, from the start region
+    pub regions: Vec<u32>,
+    /// Next best routes, cheapest first, when alternatives were requested
+    pub alternatives: Vec<PathAlternative>,
     pub stats: PathResultStats,
+    /// Unit of the distances
+    pub unit: DistanceUnit,
+}
+
+/// A route of the Pareto front, with all its metrics
+#[derive(serde::Serialize, Debug, ToSchema)]
+pub struct ParetoRoute {
+    /// Cost of the route in the fuel optimization mode, in hundredths as
+    /// the stats cost of a path
+    pub fuel: i64,
+    /// Total distance, in the unit of the result
+    pub distance: f64,
+    /// Number of connections
+    pub hops: i64,
+    pub path: Vec<PathResultConnection>,
+}
+
+#[derive(serde::Serialize, Debug, ToSchema)]
+pub struct ParetoResult {
+    pub status: PathResultStatus,
+    /// Routes no other route beats on fuel, distance and hops at once,
+    /// by increasing fuel cost
+    pub routes: Vec<ParetoRoute>,
+    pub total_time: u128,
+    pub visited: u64,
+    /// Unit of the distances
+    pub unit: DistanceUnit,
+}
+
+#[derive(serde::Serialize, Debug, ToSchema)]
+pub struct ItineraryLeg {
+    pub from: u32,
+    pub to: u32,
+    pub status: PathResultStatus,
+    /// Index of the leg's first connection in the itinerary path
+    pub start: usize,
+    /// Number of connections in the leg
+    pub length: usize,
+    pub stats: PathResultStats,
+}
+
+#[derive(serde::Serialize, Debug, ToSchema)]
+pub struct ItineraryResult {
+    /// Found when every leg was found, otherwise the status of the failing leg
+    pub status: PathResultStatus,
+    pub path: Vec<PathResultConnection>,
+    /// Regions the route goes through, in order, from the start region
+    pub regions: Vec<u32>,
+    pub legs: Vec<ItineraryLeg>,
+    /// Sum of the leg costs, same unit as their stats cost
+    pub cost: i64,
+    /// Unit of the distances
+    pub unit: DistanceUnit,
+}
+
+#[derive(serde::Serialize, Debug, ToSchema)]
+pub struct TourResult {
+    /// Found when every leg of the tour was found, NotFound (or Timeout)
+    /// when some stop cannot be part of the tour, or when the tank cannot
+    /// sustain a leg with the fuel the previous ones left
+    pub status: PathResultStatus,
+    /// Systems in visiting order, starting with the first requested system
+    /// (and ending with it on round trips)
+    pub order: Vec<u32>,
+    /// Whether the order is proven optimal, or comes from a heuristic
+    pub exact: bool,
+    pub path: Vec<PathResultConnection>,
+    /// Regions the route goes through, in order, from the start region
+    pub regions: Vec<u32>,
+    pub legs: Vec<ItineraryLeg>,
+    /// Sum of the leg costs, same unit as their stats cost
+    pub cost: i64,
+    /// When no order visits every stop, a stop no route was found to (or,
+    /// on round trips, from)
+    pub unreachable: Option<u32>,
+    /// Unit of the distances
+    pub unit: DistanceUnit,
 }
 
 #[derive(serde::Serialize, Debug, ToSchema)]
 pub struct NearResult {
     pub connections: Vec<PathResultConnection>,
+    /// Unit of the distances
+    pub unit: DistanceUnit,
+}
+
+/// A solar system and where it is
+#[derive(serde::Serialize, Debug, ToSchema)]
+pub struct SystemInfo {
+    pub id: u32,
+    pub name: Option<String>,
+    pub constellation: Option<u32>,
+    pub region: Option<u32>,
+}
+
+#[derive(serde::Serialize, Debug, ToSchema)]
+pub struct SystemsResult {
+    pub systems: Vec<SystemInfo>,
 }
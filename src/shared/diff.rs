@@ -0,0 +1,259 @@
+use std::collections::BTreeSet;
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use super::data::*;
+use super::tools;
+
+/// A system, with its name when known
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffSystem {
+    pub id: u32,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MovedSystem {
+    pub system: DiffSystem,
+    /// How far the system moved, in the unit of the diff
+    pub distance: f64,
+}
+
+/// A gate or link, from the lowest system id to the highest unless it is
+/// one-way
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffGate {
+    pub from: DiffSystem,
+    pub to: DiffSystem,
+    pub conn_type: ConnType,
+}
+
+/// Systems that can be reached with a jump from a system, in one star map
+/// and not in the other
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NeighbourhoodChange {
+    pub system: DiffSystem,
+    pub added: Vec<DiffSystem>,
+    pub removed: Vec<DiffSystem>,
+}
+
+/// What changed from a star map to another
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StarMapDiff {
+    pub added_systems: Vec<DiffSystem>,
+    pub removed_systems: Vec<DiffSystem>,
+    pub moved_systems: Vec<MovedSystem>,
+    pub added_gates: Vec<DiffGate>,
+    pub removed_gates: Vec<DiffGate>,
+    /// Jump distance the neighbourhoods are compared with
+    pub jump_distance: u16,
+    /// Systems of both star maps whose jump neighbours changed
+    pub neighbourhoods: Vec<NeighbourhoodChange>,
+    /// Unit of the distances
+    pub unit: DistanceUnit,
+}
+
+impl StarMapDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_systems.is_empty()
+            && self.removed_systems.is_empty()
+            && self.moved_systems.is_empty()
+            && self.added_gates.is_empty()
+            && self.removed_gates.is_empty()
+            && self.neighbourhoods.is_empty()
+    }
+}
+
+fn system(star_map: &StarMap, id: SolarSystemId) -> DiffSystem {
+    DiffSystem {
+        id: tools::u16_to_system_id(id),
+        name: star_map.name(id).map(String::from),
+    }
+}
+
+fn ids(star_map: &StarMap) -> BTreeSet<SolarSystemId> {
+    star_map.stars().iter().map(|star| star.id).collect()
+}
+
+/// Gates and links of the game data as pairs of systems, whatever their
+/// ids: ids change from a build to another
+fn gates(star_map: &StarMap) -> BTreeSet<(SolarSystemId, SolarSystemId, ConnType)> {
+    star_map
+        .stars()
+        .iter()
+        .flat_map(|star| {
+            star_map
+                .connections(star.id)
+                .filter(|conn| conn.conn_type != ConnType::SmartGate)
+                .map(move |conn| {
+                    if conn.conn_type.two_way() {
+                        let (from, to) = (star.id.min(conn.target), star.id.max(conn.target));
+                        (from, to, conn.conn_type)
+                    } else {
+                        (star.id, conn.target, conn.conn_type)
+                    }
+                })
+        })
+        .collect()
+}
+
+fn neighbours(
+    star_map: &StarMap,
+    id: SolarSystemId,
+    jump_distance: u16,
+) -> BTreeSet<SolarSystemId> {
+    star_map
+        .jumps(&star_map[&id], jump_distance)
+        .into_iter()
+        .map(|conn| conn.target)
+        .collect()
+}
+
+/// Compare two star maps. Names are taken from the star map the system is
+/// in, the new one when it is in both.
+pub fn diff_star_maps(
+    old: &StarMap,
+    new: &StarMap,
+    jump_distance: u16,
+    unit: DistanceUnit,
+) -> StarMapDiff {
+    let old_ids = ids(old);
+    let new_ids = ids(new);
+    let both: Vec<SolarSystemId> = old_ids.intersection(&new_ids).copied().collect();
+    let named = |id: SolarSystemId| {
+        if new.contains_key(&id) {
+            system(new, id)
+        } else {
+            system(old, id)
+        }
+    };
+    let gate = |(from, to, conn_type): &(SolarSystemId, SolarSystemId, ConnType)| DiffGate {
+        from: named(*from),
+        to: named(*to),
+        conn_type: conn_type.clone(),
+    };
+
+    let moved_systems = both
+        .iter()
+        .filter_map(|id| {
+            let distance = unit.get(old[id].distance(&new[id]));
+            (distance > 0.0).then(|| MovedSystem {
+                system: system(new, *id),
+                distance,
+            })
+        })
+        .collect();
+
+    let old_gates = gates(old);
+    let new_gates = gates(new);
+
+    let neighbourhoods = both
+        .par_iter()
+        .filter_map(|id| {
+            let before = neighbours(old, *id, jump_distance);
+            let after = neighbours(new, *id, jump_distance);
+            if before == after {
+                return None;
+            }
+            Some(NeighbourhoodChange {
+                system: system(new, *id),
+                added: after.difference(&before).map(|id| named(*id)).collect(),
+                removed: before.difference(&after).map(|id| named(*id)).collect(),
+            })
+        })
+        .collect();
+
+    StarMapDiff {
+        added_systems: new_ids
+            .difference(&old_ids)
+            .map(|id| system(new, *id))
+            .collect(),
+        removed_systems: old_ids
+            .difference(&new_ids)
+            .map(|id| system(old, *id))
+            .collect(),
+        moved_systems,
+        added_gates: new_gates.difference(&old_gates).map(gate).collect(),
+        removed_gates: old_gates.difference(&new_gates).map(gate).collect(),
+        jump_distance,
+        neighbourhoods,
+        unit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::LY;
+    use super::*;
+
+    /// Stars on a line, at positions counted in steps of 10.1 ly, with gates
+    fn line(
+        positions: &[(SolarSystemId, f64)],
+        gates: &[(SolarSystemId, SolarSystemId)],
+    ) -> StarMap {
+        let entries = positions
+            .iter()
+            .map(|&(id, x)| StarEntry {
+                star: Star::new(id, x * 10.1 * LY, 0.0, 0.0),
+                name: format!("Star {}", id),
+                connections: gates
+                    .iter()
+                    .filter_map(|&(a, b)| {
+                        if id == a {
+                            Some(b)
+                        } else if id == b {
+                            Some(a)
+                        } else {
+                            None
+                        }
+                    })
+                    .map(|target| Connection {
+                        id: target as u32,
+                        conn_type: ConnType::Gate,
+                        jump_type: 0,
+                        distance: 10.0,
+                        target,
+                    })
+                    .collect(),
+            })
+            .collect();
+        StarMap::new(entries, &BuildSource::default()).unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = line(&[(0, 0.0), (1, 1.0), (2, 2.0)], &[(0, 1), (1, 2)]);
+        assert!(diff_star_maps(&old, &old, 15, DistanceUnit::LightYear).is_empty());
+
+        let new = line(&[(0, 0.0), (1, 1.6), (3, 3.0)], &[(1, 0), (1, 3)]);
+        let diff = diff_star_maps(&old, &new, 15, DistanceUnit::LightYear);
+        let ids = |systems: &[DiffSystem]| systems.iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(ids(&diff.added_systems), vec![30000003]);
+        assert_eq!(ids(&diff.removed_systems), vec![30000002]);
+        assert_eq!(diff.removed_systems[0].name.as_deref(), Some("Star 2"));
+        assert_eq!(diff.moved_systems.len(), 1);
+        assert_eq!(diff.moved_systems[0].system.id, 30000001);
+        assert!((diff.moved_systems[0].distance - 6.06).abs() < 0.01);
+        let diff_km = diff_star_maps(&old, &new, 15, DistanceUnit::Kilometer);
+        assert!((diff_km.moved_systems[0].distance * 1000.0 / LY - 6.06).abs() < 0.01);
+        // The gate between 0 and 1 is the same in both
+        assert_eq!(diff.added_gates.len(), 1);
+        assert_eq!(diff.added_gates[0].to.id, 30000003);
+        assert_eq!(diff.removed_gates.len(), 1);
+        assert_eq!(diff.removed_gates[0].to.id, 30000002);
+        // 0 can no longer jump to 1, 1 jumps to 3 instead of 0 and 2
+        let changes: Vec<(u32, Vec<u32>, Vec<u32>)> = diff
+            .neighbourhoods
+            .iter()
+            .map(|c| (c.system.id, ids(&c.added), ids(&c.removed)))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (30000000, vec![], vec![30000001]),
+                (30000001, vec![30000003], vec![30000000, 30000002]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use std::io::Write;
+
+use serde::Serialize;
+
+use super::data::*;
+use super::tools;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Graphml,
+    Dot,
+    Json,
+    /// Two files, one for the nodes and one for the edges
+    Csv,
+}
+
+/// Which connections become edges
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub conn_types: Vec<ConnType>,
+    /// Longest jump exported, only used when jumps are exported
+    pub jump_distance: u16,
+    /// Unit of the edge distances
+    pub unit: DistanceUnit,
+}
+
+/// A star, with its center in meters
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportNode {
+    pub id: u32,
+    pub name: Option<String>,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub constellation: Option<u32>,
+    pub region: Option<u32>,
+}
+
+/// A connection, from the star it leaves. Gates and jumps go both ways, so
+/// they are exported once per direction; one-way links only once.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportEdge {
+    pub id: ConnectionId,
+    pub from: u32,
+    pub to: u32,
+    pub conn_type: ConnType,
+    /// In the unit of the export
+    pub distance: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<ExportNode>,
+    pub edges: Vec<ExportEdge>,
+    /// Unit of the edge distances
+    pub unit: DistanceUnit,
+}
+
+/// The stars of a star map, by id, and their connections of the exported
+/// types
+pub fn graph(star_map: &StarMap, options: &ExportOptions) -> Graph {
+    let mut stars: Vec<&Star> = star_map.stars().iter().collect();
+    stars.sort_unstable_by_key(|star| star.id);
+    let jumps = options.conn_types.contains(&ConnType::Jump);
+    let nodes = stars
+        .iter()
+        .map(|star| ExportNode {
+            id: tools::u16_to_system_id(star.id),
+            name: star_map.name(star.id).map(String::from),
+            x: star.x,
+            y: star.y,
+            z: star.z,
+            constellation: star.constellation(),
+            region: star.region(),
+        })
+        .collect();
+    let edges = stars
+        .iter()
+        .flat_map(|star| {
+            let stored = star_map
+                .connections(star.id)
+                .filter(|conn| options.conn_types.contains(&conn.conn_type));
+            let jumps = if jumps {
+                star_map.jumps(star, options.jump_distance)
+            } else {
+                vec![]
+            };
+            stored.chain(jumps).map(|conn| ExportEdge {
+                id: conn.id,
+                from: tools::u16_to_system_id(star.id),
+                to: tools::u16_to_system_id(conn.target),
+                conn_type: conn.conn_type,
+                distance: options.unit.convert_light_years(conn.distance),
+            })
+        })
+        .collect();
+    Graph {
+        nodes,
+        edges,
+        unit: options.unit,
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn write_graphml(graph: &Graph, out: &mut impl Write) -> anyhow::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    for (key, target, name, kind) in [
+        ("name", "node", "name", "string"),
+        ("x", "node", "x", "double"),
+        ("y", "node", "y", "double"),
+        ("z", "node", "z", "double"),
+        ("constellation", "node", "constellation", "long"),
+        ("region", "node", "region", "long"),
+        ("conn_id", "edge", "id", "long"),
+        ("conn_type", "edge", "conn_type", "string"),
+        ("distance", "edge", "distance", "double"),
+    ] {
+        writeln!(
+            out,
+            r#"  <key id="{}" for="{}" attr.name="{}" attr.type="{}"/>"#,
+            key, target, name, kind
+        )?;
+    }
+    writeln!(out, r#"  <graph id="starmap" edgedefault="directed">"#)?;
+    for node in &graph.nodes {
+        writeln!(out, r#"    <node id="{}">"#, node.id)?;
+        if let Some(name) = &node.name {
+            writeln!(out, r#"      <data key="name">{}</data>"#, xml_escape(name))?;
+        }
+        writeln!(out, r#"      <data key="x">{}</data>"#, node.x)?;
+        writeln!(out, r#"      <data key="y">{}</data>"#, node.y)?;
+        writeln!(out, r#"      <data key="z">{}</data>"#, node.z)?;
+        if let Some(constellation) = node.constellation {
+            writeln!(
+                out,
+                r#"      <data key="constellation">{}</data>"#,
+                constellation
+            )?;
+        }
+        if let Some(region) = node.region {
+            writeln!(out, r#"      <data key="region">{}</data>"#, region)?;
+        }
+        writeln!(out, "    </node>")?;
+    }
+    for edge in &graph.edges {
+        writeln!(
+            out,
+            r#"    <edge source="{}" target="{}">"#,
+            edge.from, edge.to
+        )?;
+        writeln!(out, r#"      <data key="conn_id">{}</data>"#, edge.id)?;
+        writeln!(
+            out,
+            r#"      <data key="conn_type">{}</data>"#,
+            edge.conn_type.name()
+        )?;
+        writeln!(
+            out,
+            r#"      <data key="distance">{}</data>"#,
+            edge.distance
+        )?;
+        writeln!(out, "    </edge>")?;
+    }
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")?;
+    Ok(())
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub fn write_dot(graph: &Graph, out: &mut impl Write) -> anyhow::Result<()> {
+    writeln!(out, "digraph starmap {{")?;
+    for node in &graph.nodes {
+        let label = node.name.as_deref().map(dot_escape).unwrap_or_default();
+        write!(
+            out,
+            r#"  {} [label="{}", x={}, y={}, z={}"#,
+            node.id, label, node.x, node.y, node.z
+        )?;
+        if let Some(constellation) = node.constellation {
+            write!(out, ", constellation={}", constellation)?;
+        }
+        if let Some(region) = node.region {
+            write!(out, ", region={}", region)?;
+        }
+        writeln!(out, "];")?;
+    }
+    for edge in &graph.edges {
+        writeln!(
+            out,
+            "  {} -> {} [id={}, conn_type={}, distance={}];",
+            edge.from,
+            edge.to,
+            edge.id,
+            edge.conn_type.name(),
+            edge.distance
+        )?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+pub fn write_json(graph: &Graph, out: &mut impl Write) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *out, graph)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+pub fn write_csv(
+    graph: &Graph,
+    nodes: &mut impl Write,
+    edges: &mut impl Write,
+) -> anyhow::Result<()> {
+    writeln!(nodes, "id,name,x,y,z,constellation,region")?;
+    for node in &graph.nodes {
+        writeln!(
+            nodes,
+            "{},{},{},{},{},{},{}",
+            node.id,
+            csv_field(node.name.as_deref().unwrap_or_default()),
+            node.x,
+            node.y,
+            node.z,
+            optional(node.constellation),
+            optional(node.region)
+        )?;
+    }
+    writeln!(edges, "id,from,to,conn_type,distance")?;
+    for edge in &graph.edges {
+        writeln!(
+            edges,
+            "{},{},{},{},{}",
+            edge.id,
+            edge.from,
+            edge.to,
+            edge.conn_type.name(),
+            edge.distance
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::LY;
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let entries = (0..3u16)
+            .map(|id| StarEntry {
+                star: Star::new(id, id as f64 * 10.1 * LY, 0.0, 0.0)
+                    .with_hierarchy(20000001, 10000001),
+                name: format!("Star \"{}\", <{}>", id, id),
+                connections: match id {
+                    0 => vec![Connection {
+                        id: 0,
+                        conn_type: ConnType::Gate,
+                        jump_type: 0,
+                        distance: 10.0,
+                        target: 1,
+                    }],
+                    1 => vec![Connection {
+                        id: 1,
+                        conn_type: ConnType::Gate,
+                        jump_type: 0,
+                        distance: 10.0,
+                        target: 0,
+                    }],
+                    _ => vec![],
+                },
+            })
+            .collect();
+        let star_map = StarMap::new(entries, &BuildSource::default()).unwrap();
+
+        let gates = graph(
+            &star_map,
+            &ExportOptions {
+                conn_types: vec![ConnType::Gate],
+                jump_distance: 0,
+                unit: DistanceUnit::LightYear,
+            },
+        );
+        assert_eq!(gates.nodes.len(), 3);
+        assert_eq!(gates.edges.len(), 2);
+        let jumps = graph(
+            &star_map,
+            &ExportOptions {
+                conn_types: vec![ConnType::Jump],
+                jump_distance: 15,
+                unit: DistanceUnit::Kilometer,
+            },
+        );
+        // 0 <-> 1 and 1 <-> 2, but not 0 <-> 2
+        assert_eq!(jumps.edges.len(), 4);
+        assert!(jumps
+            .edges
+            .iter()
+            .all(|edge| edge.conn_type == ConnType::Jump));
+        assert!((jumps.edges[0].distance * 1000.0 / LY - 10.1).abs() < 0.01);
+
+        let mut out = Vec::new();
+        write_graphml(&gates, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains(r#"<edge source="30000000" target="30000001">"#));
+        assert!(out.contains("Star &quot;0&quot;, &lt;0&gt;"));
+
+        let mut out = Vec::new();
+        write_dot(&gates, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("30000001 -> 30000000 [id=1, conn_type=gate, distance=10];"));
+        assert!(out.contains(r#"label="Star \"2\", <2>""#));
+
+        let mut out = Vec::new();
+        write_json(&gates, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["edges"][0]["conn_type"], "gate");
+        assert_eq!(json["nodes"][2]["region"], 10000001);
+
+        let (mut nodes, mut edges) = (Vec::new(), Vec::new());
+        write_csv(&gates, &mut nodes, &mut edges).unwrap();
+        let nodes = String::from_utf8(nodes).unwrap();
+        assert!(nodes.contains(r#"30000001,"Star ""1"", <1>",95"#));
+        let edges = String::from_utf8(edges).unwrap();
+        assert_eq!(
+            edges,
+            "id,from,to,conn_type,distance\n0,30000000,30000001,gate,10\n1,30000001,30000000,gate,10\n"
+        );
+    }
+}
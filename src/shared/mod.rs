@@ -1,7 +1,17 @@
 pub mod api;
 pub mod astar;
+pub mod build;
 pub mod data;
+pub mod diff;
+pub mod export;
+pub mod pareto;
+pub mod patch;
 pub mod path;
 pub mod raw;
 pub mod search;
-pub mod tools;
\ No newline at end of file
+pub mod spatial;
+#[cfg(test)]
+mod test_utils;
+pub mod tools;
+pub mod tour;
+pub mod validate;
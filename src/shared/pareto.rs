@@ -0,0 +1,224 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::time::Instant;
+
+use super::astar::SearchLimits;
+use super::data::*;
+use super::path;
+
+/// Search stops with a timeout once this many labels have been created
+const MAX_LABELS: usize = 2_000_000;
+
+/// Fuel, distance and hops
+type Costs = [i64; 3];
+
+/// A partial route: how it reached a system and what it cost so far
+struct Label {
+    conn: Connection,
+    costs: Costs,
+    parent: Option<usize>,
+    alive: bool,
+}
+
+/// True when `a` is at least as good as `b` on every metric
+fn covers(a: &Costs, b: &Costs) -> bool {
+    a.iter().zip(b).all(|(a, b)| a <= b)
+}
+
+fn add(a: &Costs, b: &Costs) -> Costs {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+/// Find every route between two systems that no other route beats on fuel,
+/// distance and hops at once.
+///
+/// This is a multi-objective label-correcting search: each system keeps
+/// the partial routes reaching it that are not dominated by another one,
+/// and partial routes that cannot improve on the routes already reaching
+/// the end are dropped. Fuel and distance use the costs of the `fuel` and
+/// `distance` modes. The optimization mode, algorithm and tank of the
+/// options are ignored.
+pub fn calc_pareto(
+    star_map: &StarMap,
+    smart_gates_map: &SmartGatesMap,
+    start: &Star,
+    end: &Star,
+    options: &PathOptions,
+) -> ParetoResult {
+    let start_time = Instant::now();
+    // Admissible estimate of the costs left from a system
+    let lower_bound = |system: SolarSystemId| -> Costs {
+        if system == end.id {
+            return [0, 0, 0];
+        }
+        let d = path::distance_estimate(star_map[&system].distance(end));
+        [0, d, 1]
+    };
+
+    let mut labels = vec![Label {
+        conn: Connection {
+            id: 0,
+            conn_type: ConnType::Jump,
+            jump_type: 0,
+            distance: 0.0,
+            target: start.id,
+        },
+        costs: [0, 0, 0],
+        parent: None,
+        alive: true,
+    }];
+    let mut by_system: HashMap<SolarSystemId, Vec<usize>> = HashMap::from([(start.id, vec![0])]);
+    let mut queue = BinaryHeap::from([Reverse((lower_bound(start.id), 0))]);
+    let mut status = PathResultStatus::Found;
+    let mut visited = 0;
+
+    while let Some(Reverse((_, index))) = queue.pop() {
+        if !labels[index].alive {
+            continue;
+        }
+        if options.limits.reached(start_time) || labels.len() >= MAX_LABELS {
+            status = PathResultStatus::Timeout;
+            break;
+        }
+        visited += 1;
+        options.limits.visited.add();
+        let system = labels[index].conn.target;
+        if system == end.id {
+            // Going further can only make the route worse
+            continue;
+        }
+        let costs = labels[index].costs;
+        for conn in path::connections(star_map, smart_gates_map, system, options) {
+            let next = add(
+                &costs,
+                &[
+                    path::objective_cost(&conn, PathOptimize::Fuel, options),
+                    path::objective_cost(&conn, PathOptimize::Distance, options),
+                    1,
+                ],
+            );
+            // Cannot beat a route already reaching the end
+            let bound = add(&next, &lower_bound(conn.target));
+            let front = by_system
+                .get(&end.id)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            if front.iter().any(|&i| covers(&labels[i].costs, &bound)) {
+                continue;
+            }
+            let here = by_system.entry(conn.target).or_default();
+            if here.iter().any(|&i| covers(&labels[i].costs, &next)) {
+                continue;
+            }
+            here.retain(|&i| {
+                let dominated = covers(&next, &labels[i].costs);
+                labels[i].alive &= !dominated;
+                !dominated
+            });
+            here.push(labels.len());
+            queue.push(Reverse((bound, labels.len())));
+            labels.push(Label {
+                conn: conn.clone(),
+                costs: next,
+                parent: Some(index),
+                alive: true,
+            });
+        }
+    }
+
+    let mut routes: Vec<ParetoRoute> = by_system
+        .get(&end.id)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .map(|&index| {
+            let mut path = vec![];
+            let mut current = Some(index);
+            while let Some(i) = current {
+                path.push(labels[i].conn.clone());
+                current = labels[i].parent;
+            }
+            // Skip the connection invented to start the search
+            path.pop();
+            path.reverse();
+            let [fuel, _, hops] = labels[index].costs;
+            ParetoRoute {
+                fuel,
+                distance: options
+                    .unit
+                    .convert_light_years(path.iter().map(|conn| conn.distance).sum()),
+                hops,
+                path: path::to_result_connections(star_map, &path, options),
+            }
+        })
+        .collect();
+    routes.sort_by(|a, b| {
+        a.fuel
+            .cmp(&b.fuel)
+            .then(a.distance.total_cmp(&b.distance))
+            .then(a.hops.cmp(&b.hops))
+    });
+    if routes.is_empty() && status == PathResultStatus::Found {
+        status = PathResultStatus::NotFound;
+    }
+
+    ParetoResult {
+        status,
+        routes,
+        total_time: start_time.elapsed().as_millis(),
+        visited,
+        unit: options.unit,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::*;
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_pareto() {
+        // Jumps are 10, 20, 30 and 40 ly long, gates link neighbours
+        let star_map = line(true);
+        let options = PathOptions {
+            jump_distance: 50,
+            ..options(PathOptimize::Fuel)
+        };
+        let result = calc_pareto(
+            &star_map,
+            &SmartGatesMap::new(),
+            &star_map[&0],
+            &star_map[&4],
+            &options,
+        );
+        assert_eq!(result.status, PathResultStatus::Found);
+        // Each gate taken instead of a jump saves fuel but costs a hop
+        let metrics: Vec<(i64, f64, i64)> = result
+            .routes
+            .iter()
+            .map(|route| {
+                (
+                    route.fuel,
+                    (route.distance * 10.0).round() / 10.0,
+                    route.hops,
+                )
+            })
+            .collect();
+        assert_eq!(
+            metrics,
+            vec![
+                (400, 40.0, 4),
+                (2220, 40.2, 3),
+                (3130, 40.3, 2),
+                (4040, 40.4, 1)
+            ]
+        );
+        for route in &result.routes {
+            assert_eq!(route.path.len() as i64, route.hops);
+            assert_eq!(route.path.last().unwrap().target, 30000004);
+            let systems: HashSet<u32> = route.path.iter().map(|c| c.target).collect();
+            assert_eq!(systems.len(), route.path.len(), "Routes must not loop");
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::BTreeMap;
+
+use log::info;
+use serde::Deserialize;
+
+use super::data::*;
+use super::tools;
+
+/// A star added by a patch. The center is in meters, as in the star map
+/// source.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PatchStar {
+    pub id: u32,
+    pub center: [f64; 3],
+    pub name: Option<String>,
+    pub constellation_id: Option<u32>,
+    pub region_id: Option<u32>,
+}
+
+/// A star moved by a patch, to a center in meters
+#[derive(Debug, Clone, Deserialize)]
+pub struct PatchMove {
+    pub id: u32,
+    pub center: [f64; 3],
+}
+
+/// Gates go both ways, the order of the systems does not matter unless
+/// the gate is one-way
+#[derive(Debug, Clone, Deserialize)]
+pub struct PatchGate {
+    pub from: u32,
+    pub to: u32,
+    /// Only travelled from `from`, ignored when removing
+    #[serde(default)]
+    pub one_way: bool,
+}
+
+/// Changes to apply to a star map, in this order: stars are removed,
+/// added and moved, then gates are removed and added
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct StarMapPatch {
+    pub remove_stars: Vec<u32>,
+    pub add_stars: Vec<PatchStar>,
+    pub move_stars: Vec<PatchMove>,
+    pub remove_gates: Vec<PatchGate>,
+    pub add_gates: Vec<PatchGate>,
+    /// CRC32 of the patch file, recorded in the header of the patched star
+    /// map
+    #[serde(skip)]
+    pub checksum: u32,
+}
+
+impl StarMapPatch {
+    pub fn from_file(file: &str) -> anyhow::Result<Self> {
+        let file = std::fs::read_to_string(file)?;
+        Ok(StarMapPatch {
+            checksum: crc32fast::hash(file.as_bytes()),
+            ..serde_json::from_str(&file)?
+        })
+    }
+}
+
+/// Id of a system in the star map, when it can be stored
+fn star_id(id: u32) -> anyhow::Result<SolarSystemId> {
+    tools::stored_system_id(id).ok_or_else(|| anyhow::anyhow!("Invalid system id {}", id))
+}
+
+/// Gate distances in light-years, as in a full build
+fn gate_distance(from: &Star, to: &Star) -> f64 {
+    DistanceUnit::LightYear.get(from.distance(to))
+}
+
+/// Apply a patch to a star map, without building it again from its source.
+///
+/// Only the stars and gates the patch touches change: gates of removed
+/// stars are removed, gates of moved stars get their new distance, and new
+/// gates are numbered after the existing ones, their kind following the
+/// systems they link. Removing a gate removes any link between the two
+/// systems. Jumps are not stored, they
+/// follow the new positions. The patched star map keeps the source of the
+/// original one, and records the patch in its header.
+pub fn patch_star_map(star_map: &StarMap, patch: &StarMapPatch) -> anyhow::Result<StarMap> {
+    let mut entries: BTreeMap<SolarSystemId, StarEntry> = star_map
+        .entries()
+        .into_iter()
+        .map(|entry| (entry.star.id, entry))
+        .collect();
+    let mut next_id = entries
+        .values()
+        .flat_map(|entry| entry.connections.iter())
+        .filter(|conn| conn.conn_type != ConnType::SmartGate)
+        .map(|conn| conn.id + 1)
+        .max()
+        .unwrap_or(0);
+
+    for id in &patch.remove_stars {
+        let star = star_id(*id)?;
+        if entries.remove(&star).is_none() {
+            anyhow::bail!("Cannot remove unknown system {}", id);
+        }
+        for entry in entries.values_mut() {
+            entry.connections.retain(|conn| conn.target != star);
+        }
+    }
+    info!("Removed {} stars", patch.remove_stars.len());
+
+    for added in &patch.add_stars {
+        let id = star_id(added.id)?;
+        if entries.contains_key(&id) {
+            anyhow::bail!("Cannot add system {}, it already exists", added.id);
+        }
+        let [x, y, z] = added.center;
+        let star = Star::new(id, x, y, z).with_hierarchy(
+            added.constellation_id.unwrap_or(0),
+            added.region_id.unwrap_or(0),
+        );
+        entries.insert(
+            id,
+            StarEntry {
+                star,
+                name: added.name.clone().unwrap_or_default().trim().to_string(),
+                connections: vec![],
+            },
+        );
+    }
+    info!("Added {} stars", patch.add_stars.len());
+
+    for moved in &patch.move_stars {
+        let id = star_id(moved.id)?;
+        let Some(entry) = entries.get_mut(&id) else {
+            anyhow::bail!("Cannot move unknown system {}", moved.id);
+        };
+        let [x, y, z] = moved.center;
+        entry.star = Star::new(id, x, y, z)
+            .with_hierarchy(entry.star.constellation_id, entry.star.region_id);
+        let stars: BTreeMap<SolarSystemId, Star> = entries
+            .iter()
+            .map(|(id, entry)| (*id, entry.star))
+            .collect();
+        // Gates are stored on both sides, one-way links on their source
+        for entry in entries.values_mut() {
+            let from = entry.star;
+            for conn in entry.connections.iter_mut() {
+                if from.id == id || conn.target == id {
+                    conn.distance = gate_distance(&from, &stars[&conn.target]);
+                }
+            }
+        }
+    }
+    info!("Moved {} stars", patch.move_stars.len());
+
+    for gate in &patch.remove_gates {
+        let (from, to) = (star_id(gate.from)?, star_id(gate.to)?);
+        let mut removed = 0;
+        for (a, b) in [(from, to), (to, from)] {
+            if let Some(entry) = entries.get_mut(&a) {
+                let before = entry.connections.len();
+                entry
+                    .connections
+                    .retain(|conn| conn.conn_type == ConnType::SmartGate || conn.target != b);
+                removed += before - entry.connections.len();
+            }
+        }
+        if removed == 0 {
+            anyhow::bail!("No gate between {} and {}", gate.from, gate.to);
+        }
+    }
+    info!("Removed {} gates", patch.remove_gates.len());
+
+    for gate in &patch.add_gates {
+        let (from, to) = (star_id(gate.from)?, star_id(gate.to)?);
+        let (Some(a), Some(b)) = (entries.get(&from), entries.get(&to)) else {
+            anyhow::bail!("Gate to unknown system: {} -> {}", gate.from, gate.to);
+        };
+        if from == to {
+            anyhow::bail!("Gate from system {} to itself", gate.from);
+        }
+        let distance = gate_distance(&a.star, &b.star);
+        let (conn_type, sides) = if gate.one_way {
+            (ConnType::OneWay, vec![(from, to)])
+        } else {
+            (
+                ConnType::stargate(&a.star, &b.star),
+                vec![(from, to), (to, from)],
+            )
+        };
+        for (a, b) in sides {
+            let entry = entries.get_mut(&a).unwrap();
+            entry.connections.push(Connection {
+                id: next_id,
+                conn_type: conn_type.clone(),
+                jump_type: 0,
+                distance,
+                target: b,
+            });
+            next_id += 1;
+            entry
+                .connections
+                .sort_unstable_by(|a, b| a.cmp(b).then(a.id.cmp(&b.id)));
+        }
+    }
+    info!("Added {} gates", patch.add_gates.len());
+
+    StarMap::new(
+        entries.into_values().collect(),
+        &star_map.source().patched(patch.checksum),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::LY;
+    use super::*;
+
+    /// Stars 30000000 to 30000003 on a line 10.1 ly apart, with gates
+    /// between neighbours
+    fn line() -> StarMap {
+        let entries = (0..4u16)
+            .map(|id| {
+                let connections = [id.checked_sub(1), Some(id + 1).filter(|n| *n < 4)]
+                    .into_iter()
+                    .flatten()
+                    .map(|target| Connection {
+                        id: id.min(target) as u32 * 2 + (id > target) as u32,
+                        conn_type: ConnType::Gate,
+                        jump_type: 0,
+                        distance: 10.0,
+                        target,
+                    })
+                    .collect();
+                StarEntry {
+                    star: Star::new(id, id as f64 * 10.1 * LY, 0.0, 0.0),
+                    name: format!("Star {}", id),
+                    connections,
+                }
+            })
+            .collect();
+        StarMap::new(entries, &BuildSource::default()).unwrap()
+    }
+
+    /// Distances to a tenth of a light-year
+    fn tenths(conns: impl Iterator<Item = Connection>) -> Vec<(SolarSystemId, f64)> {
+        conns
+            .map(|conn| (conn.target, (conn.distance * 10.0).round() / 10.0))
+            .collect()
+    }
+
+    fn gates(star_map: &StarMap, id: SolarSystemId) -> Vec<(SolarSystemId, f64)> {
+        tenths(star_map.connections(id))
+    }
+
+    #[test]
+    fn test_patch() {
+        let star_map = line();
+        let patch: StarMapPatch = serde_json::from_str(&format!(
+            r#"{{
+                "remove_stars": [30000001],
+                "add_stars": [{{"id": 30000004, "center": [{x}, 0, 0], "name": "New"}}],
+                "move_stars": [{{"id": 30000003, "center": [{x}, {y}, 0]}}],
+                "add_gates": [{{"from": 30000000, "to": 30000004}}]
+            }}"#,
+            x = 30.5 * LY,
+            y = 20.5 * LY,
+        ))
+        .unwrap();
+        let patched = patch_star_map(&star_map, &patch).unwrap();
+        assert_eq!(gates(&star_map, 2), vec![(1, 10.0), (3, 10.0)]);
+
+        assert_eq!(patched.len(), 4);
+        assert!(patched.get(&1).is_none());
+        assert_eq!(patched.name(4), Some("New"));
+        assert_eq!(gates(&patched, 0), vec![(4, 30.5)]);
+        assert_eq!(gates(&patched, 4), vec![(0, 30.5)]);
+        // Gates of the moved star get its new distance
+        assert_eq!(gates(&patched, 2), vec![(3, 22.9)]);
+        assert_eq!(gates(&patched, 3), vec![(2, 22.9)]);
+        // New gates are numbered after the existing ones
+        assert!(patched.connections(0).all(|conn| conn.id >= 6));
+        // Jumps follow the moved star
+        let jumps = tenths(patched.jumps(&patched[&3], 21).into_iter());
+        assert_eq!(jumps, vec![(4, 20.5)]);
+
+        let patch: StarMapPatch = serde_json::from_str(
+            r#"{
+                "remove_gates": [{"from": 30000003, "to": 30000002}],
+                "add_gates": [{"from": 30000004, "to": 30000002, "one_way": true}]
+            }"#,
+        )
+        .unwrap();
+        let patched = patch_star_map(&patched, &patch).unwrap();
+        assert_eq!(gates(&patched, 2), vec![]);
+        assert_eq!(gates(&patched, 3), vec![]);
+        // One-way gates are only stored on their source
+        let one_way: Vec<Connection> = patched
+            .connections(4)
+            .filter(|conn| conn.conn_type == ConnType::OneWay)
+            .collect();
+        assert_eq!(tenths(one_way.into_iter()), vec![(2, 10.3)]);
+        // The header records the patches, the source stays the original one
+        let (before, after) = (star_map.info(), patched.info());
+        assert_eq!((before.patches, after.patches), (0, 2));
+        assert_ne!(after.patch_checksum, before.patch_checksum);
+        assert_eq!(after.source_checksum, before.source_checksum);
+
+        // Patches must match the star map
+        for patch in [
+            r#"{"remove_stars": [30000009]}"#,
+            r#"{"add_stars": [{"id": 30000002, "center": [0, 0, 0]}]}"#,
+            r#"{"remove_gates": [{"from": 30000000, "to": 30000002}]}"#,
+            r#"{"add_gates": [{"from": 30000000, "to": 30000009}]}"#,
+        ] {
+            let patch: StarMapPatch = serde_json::from_str(patch).unwrap();
+            assert!(patch_star_map(&star_map, &patch).is_err(), "{:?}", patch);
+        }
+    }
+}
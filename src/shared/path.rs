@@ -1,118 +1,511 @@
-use std::collections::HashMap;
-use std::time::Duration;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 use uom::si::f64::*;
 use uom::si::length::light_year;
 
-use super::astar;
+use super::astar::{self, SearchLimits};
 use super::data::*;
 use super::tools;
 
 // Static empty vector to avoid creating a new one on each function call
 static EMPTY_CONNECTIONS: &Vec<Connection> = &Vec::new();
 
+/// All connections leaving a system that are usable with the given jump
+/// distance: smart gates first, then the star's own gates, then the jumps
+/// in range. Avoided systems and connections are left out.
+pub(super) fn connections<'a>(
+    star_map: &'a StarMap,
+    smart_gates_map: &'a SmartGatesMap,
+    system: SolarSystemId,
+    options: &'a PathOptions,
+) -> impl Iterator<Item = Connection> + 'a {
+    let star = star_map.get(&system).unwrap();
+
+    let smart_gates = smart_gates_map.get(&system).unwrap_or(EMPTY_CONNECTIONS);
+
+    // Chain smart_gates first, then the star's connections
+    smart_gates
+        .iter()
+        .cloned()
+        .chain(star_map.connections(system))
+        .chain(star_map.jumps(star, options.jump_distance))
+        .filter(|c| !options.avoid.contains(c))
+}
+
+/// Costs are integers, so a ship's fuel is counted in hundredths of a unit
+const FUEL_COST_SCALE: f64 = 100.0;
+
+/// Weighted costs are counted in hundredths, as fuel costs
+const WEIGHT_SCALE: f64 = 100.0;
+
+/// Distances are counted in hundredths of a light-year, as fuel costs
+const DISTANCE_COST_SCALE: f64 = 100.0;
+
+/// Cost of a distance in light-years, rounded up so that the total is
+/// never below the straight line estimate
+fn distance_cost(distance: f64) -> i64 {
+    (distance * DISTANCE_COST_SCALE).ceil() as i64
+}
+
+/// Admissible estimate of the cost of covering a straight line distance
+pub(super) fn distance_estimate(d: Length) -> i64 {
+    (d.get::<light_year>() * DISTANCE_COST_SCALE).floor() as i64
+}
+
+/// Weighted cost of a connection, rounded up so that the total is never
+/// below the weighted sum the heuristic estimates
+fn weighted_cost(conn: &Connection, weights: &CostWeights) -> i64 {
+    let weight = match conn.conn_type {
+        ConnType::Jump => weights.jump * conn.distance,
+        _ => weights.kind(&conn.conn_type),
+    };
+    ((weights.hop + weight) * WEIGHT_SCALE).ceil() as i64
+}
+
+/// Lower bound of the weighted cost between two distinct systems `d` apart:
+/// at least one hop, and either a gate of some kind, or jumps covering at
+/// least the straight line distance
+fn weighted_heuristic(d: Length, weights: &CostWeights) -> i64 {
+    let weight = weights.min_link().min(weights.jump * d.get::<light_year>());
+    ((weights.hop + weight) * WEIGHT_SCALE).floor() as i64
+}
+
+/// What it costs to travel through a connection
+fn cost(conn: &Connection, options: &PathOptions) -> i64 {
+    objective_cost(conn, options.optimize, options)
+}
+
+/// What it costs to travel through a connection when optimizing for the
+/// given objective, whatever the options ask for
+pub(super) fn objective_cost(
+    conn: &Connection,
+    optimize: PathOptimize,
+    options: &PathOptions,
+) -> i64 {
+    match (optimize, &conn.conn_type) {
+        // For shortest path, we only care about the distance
+        // and don't care about the type of connection
+        (PathOptimize::Distance, _) => distance_cost(conn.distance),
+        // For fuel efficient, we penalise jumps, with the fuel they
+        // actually burn when we know the ship
+        (PathOptimize::Fuel, ConnType::Jump) => match &options.ship {
+            Some(ship) => (ship.connection_fuel(conn) * FUEL_COST_SCALE).round() as i64,
+            None => distance_cost(conn.distance),
+        },
+        // Gates and other links burn no fuel, but cost as much as a 1 ly
+        // jump so that we don't take more of them than needed, or a
+        // hundredth of a fuel unit with a ship, times the weight of their
+        // kind
+        (PathOptimize::Fuel, conn_type) => {
+            let cost = match &options.ship {
+                Some(_) => 1.0,
+                None => DISTANCE_COST_SCALE,
+            };
+            (cost * options.weights.kind(conn_type)).ceil() as i64
+        }
+        // Treat all hops the same, we want to minimise the total
+        (PathOptimize::Hops, _) => 100,
+        (PathOptimize::Weighted, _) => weighted_cost(conn, &options.weights),
+    }
+}
+
 /// Given a connection, return a list of all possible next-connections,
 /// and what each of those connections costs
 fn successors(
-    star_map: &HashMap<SolarSystemId, Star>,
+    star_map: &StarMap,
     smart_gates_map: &SmartGatesMap,
     conn: &Connection,
-    jump_distance: u16,
-    optimize: PathOptimize,
+    options: &PathOptions,
 ) -> Vec<(Connection, i64)> {
-    let star = star_map.get(&conn.target).unwrap();
-
-    let smart_gates = smart_gates_map
-        .get(&conn.target)
-        .unwrap_or(&EMPTY_CONNECTIONS);
-
-    // Chain smart_gates first, then star.connections
-    smart_gates
-        .iter()
-        .chain(star.connections.iter())
-        // take gates and short jumps - stop searching after we
-        // find a long jump
-        .take_while(|c| c.conn_type != ConnType::Jump || c.distance <= jump_distance)
+    connections(star_map, smart_gates_map, conn.target, options)
         // Turn the connection into a (connection, cost) tuple
         .map(|c| {
-            match (optimize, &c.conn_type) {
-                // For shortest path, we only care about the distance
-                // and don't care about the type of connection
-                (PathOptimize::Distance, _) => (c.clone(), c.distance as i64),
-                // For fuel efficient, we penalise jumps
-                (PathOptimize::Fuel, ConnType::Jump) => (c.clone(), c.distance as i64),
-                // Over gates, we only count half the distance
-                (PathOptimize::Fuel, ConnType::Gate) => (c.clone(), 1),
-                (PathOptimize::Fuel, ConnType::SmartGate) => (c.clone(), 1),
-                // Treat all hops the same, we want to minimise the total
-                (PathOptimize::Hops, _) => (c.clone(), 100),
-            }
+            let cost = cost(&c, options);
+            (c, cost)
         })
         .collect()
 }
 
+/// Whether the hop from `conn.target` to `system` is avoided, `conn` being
+/// its mirror stored on `system`, which has a different id
+fn mirror_avoided(
+    star_map: &StarMap,
+    system: SolarSystemId,
+    conn: &Connection,
+    avoid: &Avoid,
+) -> bool {
+    if avoid.connections.is_empty() {
+        return false;
+    }
+    if conn.conn_type == ConnType::Jump {
+        return star_map
+            .reverse_jump_id(conn.id)
+            .is_some_and(|id| avoid.connections.contains(&id));
+    }
+    star_map.connections(conn.target).any(|c| {
+        c.target == system && c.conn_type == conn.conn_type && avoid.connections.contains(&c.id)
+    })
+}
+
+/// Given a system, return the systems it can be reached from and what
+/// that last hop costs. This walks the reverse graph: two-way connections
+/// and jumps are symmetric, one-way links come from `StarMap::incoming` and
+/// `reverse_smart_gates`.
+fn predecessors(
+    star_map: &StarMap,
+    reverse: &SmartGatesMap,
+    system: SolarSystemId,
+    options: &PathOptions,
+) -> Vec<(SolarSystemId, i64)> {
+    let star = star_map.get(&system).unwrap();
+
+    let incoming = star_map
+        .incoming(system)
+        .iter()
+        .chain(reverse.get(&system).unwrap_or(EMPTY_CONNECTIONS))
+        // Reversed links keep their id
+        .filter(|c| !options.avoid.contains(c))
+        .cloned();
+
+    // One-way links stored in the star map are outgoing only
+    let mirrored = star_map
+        .connections(system)
+        .filter(|c| c.conn_type.two_way())
+        .chain(star_map.jumps(star, options.jump_distance))
+        .filter(|c| {
+            !options.avoid.systems.contains(&c.target)
+                && !mirror_avoided(star_map, system, c, &options.avoid)
+        });
+
+    incoming
+        .chain(mirrored)
+        .map(|c| (c.target, cost(&c, options)))
+        .collect()
+}
+
 /// Heuristic function for A* pathfinding
 /// - Return an approximation of the cost from this connection to the end
 /// - Must not return greater than the actual cost, or the path will be suboptimal
 ///   - Remember that in "optimise for fuel" mode, actual cost might be 1
-pub fn heuristic(
-    star_map: &HashMap<SolarSystemId, Star>,
-    conn: &Connection,
-    end: &Star,
-    optimize: PathOptimize,
+pub fn heuristic(star_map: &StarMap, conn: &Connection, end: &Star, options: &PathOptions) -> i64 {
+    if conn.conn_type != ConnType::Jump && options.optimize == PathOptimize::Fuel {
+        return 0;
+    }
+    if options.optimize == PathOptimize::Weighted && conn.target == end.id {
+        return 0;
+    }
+    let d = star_map.get(&conn.target).unwrap().distance(end);
+    match (options.optimize, &options.ship) {
+        (PathOptimize::Fuel, Some(ship)) => (ship.jump_fuel(d) * FUEL_COST_SCALE) as i64,
+        (PathOptimize::Weighted, _) => weighted_heuristic(d, &options.weights),
+        (PathOptimize::Distance | PathOptimize::Fuel, _) => distance_estimate(d),
+        (PathOptimize::Hops, _) => d.get::<light_year>() as i64,
+    }
+}
+
+/// Heuristic used by the bidirectional search, where nodes are systems
+/// rather than connections: an admissible estimate of the cost between
+/// two systems.
+fn system_heuristic(
+    star_map: &StarMap,
+    system: SolarSystemId,
+    other: &Star,
+    options: &PathOptions,
 ) -> i64 {
-    if conn.conn_type != ConnType::Jump && optimize == PathOptimize::Fuel {
+    if system == other.id {
         return 0;
     }
-    let d = star_map
-        .get(&conn.target)
-        .unwrap()
-        .distance(end)
-        .get::<light_year>();
-    return d as i64;
+    match options.optimize {
+        // A single gate could get us there
+        PathOptimize::Fuel => 0,
+        PathOptimize::Distance => distance_estimate(star_map.get(&system).unwrap().distance(other)),
+        // At least one more hop
+        PathOptimize::Hops => 100,
+        PathOptimize::Weighted => weighted_heuristic(
+            star_map.get(&system).unwrap().distance(other),
+            &options.weights,
+        ),
+    }
 }
 
-pub fn calc_path(
+/// Turn a list of systems into the cheapest connections linking them,
+/// starting with the invented connection to the first system
+fn systems_to_connections(
+    star_map: &StarMap,
+    smart_gates_map: &SmartGatesMap,
+    init_conn: &Connection,
+    systems: &[SolarSystemId],
+    options: &PathOptions,
+) -> Vec<Connection> {
+    let mut path = vec![init_conn.clone()];
+    for pair in systems.windows(2) {
+        let conn = connections(star_map, smart_gates_map, pair[0], options)
+            .filter(|c| c.target == pair[1])
+            .min_by_key(|c| cost(c, options))
+            .unwrap(); // The search only follows existing connections
+        path.push(conn);
+    }
+    path
+}
+
+/// Fuel burnt travelling through a connection: the ship's fuel model when
+/// known, otherwise the jump distance in light-years. Gates and other
+/// links are free.
+fn connection_fuel(conn: &Connection, options: &PathOptions) -> f64 {
+    match (&options.ship, &conn.conn_type) {
+        (Some(ship), _) => ship.connection_fuel(conn),
+        (None, ConnType::Jump) => conn.distance,
+        (None, _) => 0.0,
+    }
+}
+
+/// Tank levels are tracked in hundredths of a unit. Burns are rounded up
+/// and capacities down, so a route is never reported feasible by rounding.
+fn burn_units(fuel: f64) -> u64 {
+    (fuel * FUEL_COST_SCALE).ceil() as u64
+}
+
+fn tank_units(fuel: f64) -> u64 {
+    (fuel * FUEL_COST_SCALE).floor() as u64
+}
+
+/// Search node when the tank is tracked: the same connection reached with
+/// different fuel levels leads to different futures
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct TankState {
+    conn: Connection,
+    fuel: u64,
+}
+
+/// Forward A* where each jump burns fuel from the tank, and arriving in a
+/// refuel system fills it up again
+fn find_tank_path(
+    star_map: &StarMap,
+    smart_gates_map: &SmartGatesMap,
+    init_conn: Connection,
+    end: &Star,
+    options: &PathOptions,
+    tank: &Tank,
+) -> astar::PathFindResult<Connection, i64> {
+    let capacity = tank_units(tank.capacity);
+    let fill = |system: SolarSystemId, fuel: u64| {
+        if tank.refuel_systems.contains(&system) {
+            capacity
+        } else {
+            fuel
+        }
+    };
+    let init = TankState {
+        fuel: fill(init_conn.target, tank_units(tank.fuel).min(capacity)),
+        conn: init_conn,
+    };
+    // Most fuel each connection was expanded with. States are expanded
+    // cheapest first, so reaching a connection again later with no more
+    // fuel cannot lead anywhere better.
+    let mut expanded: HashMap<Connection, u64> = HashMap::new();
+    let path = astar::astar(
+        &init,
+        |state| {
+            match expanded.get(&state.conn) {
+                Some(&fuel) if fuel >= state.fuel => return vec![],
+                _ => expanded.insert(state.conn.clone(), state.fuel),
+            };
+            successors(star_map, smart_gates_map, &state.conn, options)
+                .into_iter()
+                .filter_map(|(c, cost)| {
+                    let fuel = state
+                        .fuel
+                        .checked_sub(burn_units(connection_fuel(&c, options)))?;
+                    Some((
+                        TankState {
+                            fuel: fill(c.target, fuel),
+                            conn: c,
+                        },
+                        cost,
+                    ))
+                })
+                .collect::<Vec<_>>()
+        },
+        |state| heuristic(star_map, &state.conn, end, options),
+        |state| state.conn.target == end.id,
+        &options.limits,
+    );
+    match path {
+        astar::PathFindResult::Found((states, cost, stats)) => astar::PathFindResult::Found((
+            states.into_iter().map(|state| state.conn).collect(),
+            cost,
+            stats,
+        )),
+        astar::PathFindResult::NotFound(stats) => astar::PathFindResult::NotFound(stats),
+        astar::PathFindResult::Timeout(stats) => astar::PathFindResult::Timeout(stats),
+    }
+}
+
+/// Pick the refuel stops along a route the tank can sustain: refuel in a
+/// system only when the fuel left cannot reach the next refuel system (or
+/// the end). Refuelling as late as possible gives the fewest stops. The
+/// tank is also filled at the last refuel system of the route, so that it
+/// arrives with as much fuel as the search found, for the next leg of an
+/// itinerary.
+///
+/// Returns the stops and the fuel left on arrival.
+fn refuel_stops(path: &[Connection], options: &PathOptions, tank: &Tank) -> (Vec<u32>, f64) {
+    let capacity = tank_units(tank.capacity);
+    let burns: Vec<u64> = path[1..]
+        .iter()
+        .map(|c| burn_units(connection_fuel(c, options)))
+        .collect();
+    // Whether the system each hop leaves from can refuel
+    let stations: Vec<bool> = path[..burns.len()]
+        .iter()
+        .map(|c| tank.refuel_systems.contains(&c.target))
+        .collect();
+
+    let last = stations.iter().rposition(|station| *station);
+    let mut stops = vec![];
+    let mut fuel = tank_units(tank.fuel).min(capacity);
+    for (i, burn) in burns.iter().enumerate() {
+        if stations[i] {
+            let next = (i + 1..burns.len())
+                .find(|&j| stations[j])
+                .unwrap_or(burns.len());
+            let last_fill = last == Some(i) && fuel < capacity;
+            if last_fill || burns[i..next].iter().sum::<u64>() > fuel {
+                fuel = capacity;
+                stops.push(tools::u16_to_system_id(path[i].target));
+            }
+        }
+        // The search only returns routes the tank can sustain
+        fuel = fuel.saturating_sub(*burn);
+    }
+    (stops, fuel as f64 / FUEL_COST_SCALE)
+}
+
+/// Run the configured search between two systems
+fn find_path(
     star_map: &StarMap,
     smart_gates_map: &SmartGatesMap,
     start: &Star,
     end: &Star,
-    jump_distance: u16,
-    optimize: PathOptimize,
-    timeout: Option<u64>,
-) -> PathResult {
+    options: &PathOptions,
+) -> astar::PathFindResult<Connection, i64> {
     let init_conn = Connection {
         id: 0,
         conn_type: ConnType::Jump,
-        distance: 0,
+        jump_type: 0,
+        distance: 0.0,
         target: start.id,
     };
-    let path = astar::astar(
-        &init_conn,
-        |conn| successors(&star_map, &smart_gates_map, conn, jump_distance, optimize),
-        |conn| heuristic(&star_map, conn, end, optimize),
-        |conn| conn.target == end.id,
-        timeout,
-    );
+    if let Some(tank) = &options.tank {
+        // The tank only makes sense going forward
+        return find_tank_path(star_map, smart_gates_map, init_conn, end, options, tank);
+    }
+    match options.algorithm {
+        PathAlgorithm::Astar => astar::astar(
+            &init_conn,
+            |conn| successors(star_map, smart_gates_map, conn, options),
+            |conn| heuristic(star_map, conn, end, options),
+            |conn| conn.target == end.id,
+            &options.limits,
+        ),
+        PathAlgorithm::Bidirectional => {
+            let reverse = reverse_smart_gates(smart_gates_map);
+            let path = astar::bidirectional_astar(
+                &start.id,
+                &end.id,
+                |system| {
+                    connections(star_map, smart_gates_map, *system, options)
+                        .map(|c| (c.target, cost(&c, options)))
+                        .collect::<Vec<_>>()
+                },
+                |system| predecessors(star_map, &reverse, *system, options),
+                |system| system_heuristic(star_map, *system, end, options),
+                |system| system_heuristic(star_map, *system, start, options),
+                &options.limits,
+            );
+            match path {
+                astar::PathFindResult::Found((systems, cost, stats)) => {
+                    let path = systems_to_connections(
+                        star_map,
+                        smart_gates_map,
+                        &init_conn,
+                        &systems,
+                        options,
+                    );
+                    astar::PathFindResult::Found((path, cost, stats))
+                }
+                astar::PathFindResult::NotFound(stats) => astar::PathFindResult::NotFound(stats),
+                astar::PathFindResult::Timeout(stats) => astar::PathFindResult::Timeout(stats),
+            }
+        }
+    }
+}
+
+pub(super) fn to_result_connections(
+    star_map: &StarMap,
+    path: &[Connection],
+    options: &PathOptions,
+) -> Vec<PathResultConnection> {
+    path.iter()
+        .map(|c| PathResultConnection {
+            conn_type: c.conn_type.clone(),
+            jump_type: Some(c.jump_type).filter(|_| c.conn_type.is_game_link()),
+            distance: options.unit.convert_light_years(c.distance),
+            target: tools::u16_to_system_id(c.target),
+            target_name: star_map.name(c.target).map(String::from),
+            target_constellation: star_map[&c.target].constellation(),
+            target_region: star_map[&c.target].region(),
+            id: c.id,
+            fuel: options.ship.map(|ship| ship.connection_fuel(c)),
+        })
+        .collect()
+}
 
+/// Regions a route goes through, in order, starting with the region of its
+/// first system. Systems of unknown region are skipped.
+pub(super) fn route_regions(start: &Star, path: &[PathResultConnection]) -> Vec<u32> {
+    let mut regions: Vec<u32> = start
+        .region()
+        .into_iter()
+        .chain(path.iter().filter_map(|c| c.target_region))
+        .collect();
+    regions.dedup();
+    regions
+}
+
+/// Fuel burnt over a whole route, when the ship is known
+fn total_fuel(path: &[PathResultConnection]) -> Option<f64> {
+    path.iter().map(|c| c.fuel).sum()
+}
+
+fn to_path_result(
+    star_map: &StarMap,
+    path: astar::PathFindResult<Connection, i64>,
+    options: &PathOptions,
+) -> PathResult {
     match path {
         astar::PathFindResult::Found((path, cost, stats)) => {
+            let (refuels, fuel_left) = match &options.tank {
+                Some(tank) => {
+                    let (refuels, fuel_left) = refuel_stops(&path, options, tank);
+                    (refuels, Some(fuel_left))
+                }
+                None => (vec![], None),
+            };
             // The first connection is the one we invented
             // to start the search, so we can skip it
-            let path = path[1..]
-                .to_vec()
-                .iter()
-                .map(|c| PathResultConnection {
-                    conn_type: c.conn_type.clone(),
-                    distance: c.distance,
-                    target: tools::u16_to_system_id(c.target),
-                    id: c.id,
-                })
-                .collect();
-            return PathResult {
+            let start = &star_map[&path[0].target];
+            let path = to_result_connections(star_map, &path[1..], options);
+            PathResult {
                 status: PathResultStatus::Found,
+                fuel: total_fuel(&path),
+                regions: route_regions(start, &path),
                 path,
+                refuels,
+                fuel_left,
+                alternatives: vec![],
                 stats: PathResultStats {
                     cost,
                     total_time: stats.total_time.as_millis(),
@@ -120,33 +513,556 @@ pub fn calc_path(
                     loop_spend: stats.loop_spend.as_millis(),
                     visited: stats.visited,
                 },
-            };
+                unit: options.unit,
+            }
         }
-        astar::PathFindResult::NotFound(stats) => {
-            return PathResult {
-                status: PathResultStatus::NotFound,
-                path: vec![],
-                stats: PathResultStats {
-                    cost: 0,
-                    total_time: stats.total_time.as_millis(),
-                    successors_spend: stats.successors_spend.as_millis(),
-                    loop_spend: stats.loop_spend.as_millis(),
-                    visited: stats.visited,
+        astar::PathFindResult::NotFound(stats) => PathResult {
+            status: PathResultStatus::NotFound,
+            path: vec![],
+            fuel: None,
+            refuels: vec![],
+            fuel_left: None,
+            regions: vec![],
+            alternatives: vec![],
+            stats: PathResultStats {
+                cost: 0,
+                total_time: stats.total_time.as_millis(),
+                successors_spend: stats.successors_spend.as_millis(),
+                loop_spend: stats.loop_spend.as_millis(),
+                visited: stats.visited,
+            },
+            unit: options.unit,
+        },
+        astar::PathFindResult::Timeout(stats) => PathResult {
+            status: PathResultStatus::Timeout,
+            path: vec![],
+            fuel: None,
+            refuels: vec![],
+            fuel_left: None,
+            regions: vec![],
+            alternatives: vec![],
+            stats: PathResultStats {
+                cost: 0,
+                total_time: stats.total_time.as_millis(),
+                successors_spend: stats.successors_spend.as_millis(),
+                loop_spend: stats.loop_spend.as_millis(),
+                visited: stats.visited,
+            },
+            unit: options.unit,
+        },
+    }
+}
+
+pub fn calc_path(
+    star_map: &StarMap,
+    smart_gates_map: &SmartGatesMap,
+    start: &Star,
+    end: &Star,
+    options: &PathOptions,
+) -> PathResult {
+    to_path_result(
+        star_map,
+        find_path(star_map, smart_gates_map, start, end, options),
+        options,
+    )
+}
+
+/// Find the best path plus up to `alternatives` next best loopless paths,
+/// using Yen's k-shortest paths algorithm on top of the A* search.
+///
+/// Each alternative deviates from an already accepted route at some system
+/// (the spur), and is searched again with the next connection of the
+/// accepted routes sharing the same prefix removed, and the prefix systems
+/// blocked so the route cannot loop back on itself.
+///
+/// Alternatives are not searched when a fuel tank is set, only the best path
+/// is returned.
+pub fn calc_alternatives(
+    star_map: &StarMap,
+    smart_gates_map: &SmartGatesMap,
+    start: &Star,
+    end: &Star,
+    options: &PathOptions,
+    alternatives: usize,
+) -> PathResult {
+    let start_time = Instant::now();
+    let best = find_path(star_map, smart_gates_map, start, end, options);
+    let astar::PathFindResult::Found((best_path, best_cost, _)) = &best else {
+        return to_path_result(star_map, best, options);
+    };
+    if options.tank.is_some() {
+        return to_path_result(star_map, best, options);
+    }
+
+    // Accepted routes, and candidates waiting to be accepted
+    let mut routes: Vec<(Vec<Connection>, i64)> = vec![(best_path.clone(), *best_cost)];
+    let mut candidates: Vec<(Vec<Connection>, i64)> = Vec::new();
+    let same_ids = |a: &[Connection], b: &[Connection]| {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.id == y.id)
+    };
+
+    'routes: while routes.len() <= alternatives {
+        let previous = routes.last().unwrap().0.clone();
+        // The last connection reaches the end, there is nothing to deviate from
+        for spur in 0..previous.len() - 1 {
+            if options.limits.reached(start_time) {
+                break 'routes;
+            }
+            let root = &previous[..=spur];
+            let blocked_connections: HashSet<ConnectionId> = routes
+                .iter()
+                .filter(|(path, _)| path.len() > spur + 1 && same_ids(&path[..=spur], root))
+                .map(|(path, _)| path[spur + 1].id)
+                .collect();
+            let blocked_systems: HashSet<SolarSystemId> =
+                root[..spur].iter().map(|c| c.target).collect();
+
+            let remaining = options.limits.remaining(start_time.elapsed());
+            let spur_path = astar::astar(
+                &previous[spur],
+                |conn| {
+                    successors(star_map, smart_gates_map, conn, options)
+                        .into_iter()
+                        .filter(|(c, _)| {
+                            !blocked_connections.contains(&c.id)
+                                && !blocked_systems.contains(&c.target)
+                        })
                 },
+                |conn| heuristic(star_map, conn, end, options),
+                |conn| conn.target == end.id,
+                &remaining,
+            );
+            if let astar::PathFindResult::Found((spur_path, spur_cost, _)) = spur_path {
+                let root_cost: i64 = root[1..].iter().map(|c| cost(c, options)).sum();
+                let mut path = root.to_vec();
+                path.extend(spur_path.into_iter().skip(1));
+                let known = routes
+                    .iter()
+                    .chain(candidates.iter())
+                    .any(|(other, _)| same_ids(other, &path));
+                if !known {
+                    candidates.push((path, root_cost + spur_cost));
+                }
+            }
+        }
+        // Accept the cheapest candidate, keeping the earliest found on ties
+        let Some(next) = candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(i, (_, cost))| (*cost, *i))
+            .map(|(i, _)| i)
+        else {
+            break;
+        };
+        routes.push(candidates.remove(next));
+    }
+
+    let mut result = to_path_result(star_map, best, options);
+    result.alternatives = routes
+        .iter()
+        .skip(1)
+        .map(|(path, cost)| {
+            let path = to_result_connections(star_map, &path[1..], options);
+            PathAlternative {
+                cost: *cost,
+                fuel: total_fuel(&path),
+                path,
+            }
+        })
+        .collect();
+    result.stats.total_time = start_time.elapsed().as_millis();
+    result
+}
+
+/// Plan a trip through an ordered list of systems, one path search per leg.
+/// Planning stops at the first leg that cannot be completed.
+///
+/// With a fuel tank, each leg starts with the fuel left by the previous one.
+pub fn calc_itinerary(
+    star_map: &StarMap,
+    smart_gates_map: &SmartGatesMap,
+    stops: &[&Star],
+    options: &PathOptions,
+) -> ItineraryResult {
+    let mut result = ItineraryResult {
+        status: PathResultStatus::Found,
+        path: vec![],
+        regions: vec![],
+        legs: vec![],
+        cost: 0,
+        unit: options.unit,
+    };
+    let start_time = Instant::now();
+    let mut options = options.clone();
+    let limits = options.limits.clone();
+    for pair in stops.windows(2) {
+        // Legs share the limits of the whole trip
+        options.limits = limits.remaining(start_time.elapsed());
+        let leg = calc_path(star_map, smart_gates_map, pair[0], pair[1], &options);
+        if let (Some(tank), Some(fuel_left)) = (options.tank.as_mut(), leg.fuel_left) {
+            tank.fuel = fuel_left;
+        }
+        if leg.status == PathResultStatus::Found {
+            result.cost += leg.stats.cost;
+        }
+        result.legs.push(ItineraryLeg {
+            from: tools::u16_to_system_id(pair[0].id),
+            to: tools::u16_to_system_id(pair[1].id),
+            status: leg.status,
+            start: result.path.len(),
+            length: leg.path.len(),
+            stats: leg.stats,
+        });
+        if leg.status != PathResultStatus::Found {
+            result.status = leg.status;
+            break;
+        }
+        result.path.extend(leg.path);
+    }
+    if let Some(start) = stops.first() {
+        result.regions = route_regions(start, &result.path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::*;
+    use super::*;
+    use uom::si::length::meter;
+
+    #[test]
+    fn test_alternatives() {
+        let star_map = grid();
+        let smart_gates_map = SmartGatesMap::new();
+        let result = calc_alternatives(
+            &star_map,
+            &smart_gates_map,
+            &star_map[&0],
+            &star_map[&8],
+            &options(PathOptimize::Distance),
+            10,
+        );
+        assert!(matches!(result.status, PathResultStatus::Found));
+        assert_eq!(result.stats.cost, 4000);
+        // There are exactly 6 shortest routes across the grid, and the
+        // next ones must take a detour
+        assert_eq!(result.alternatives.len(), 10);
+        assert!(result.alternatives[..5].iter().all(|a| a.cost == 4000));
+        assert!(result.alternatives[5..].iter().all(|a| a.cost > 4000));
+        for alternative in &result.alternatives {
+            let mut systems: Vec<u32> = alternative.path.iter().map(|c| c.target).collect();
+            systems.push(tools::u16_to_system_id(0));
+            let count = systems.len();
+            systems.sort();
+            systems.dedup();
+            assert_eq!(systems.len(), count, "Routes must not loop");
+        }
+        let mut routes: Vec<Vec<u32>> = result
+            .alternatives
+            .iter()
+            .map(|a| a.path.iter().map(|c| c.id).collect())
+            .collect();
+        routes.push(result.path.iter().map(|c| c.id).collect());
+        let count = routes.len();
+        routes.sort();
+        routes.dedup();
+        assert_eq!(routes.len(), count, "Routes must be distinct");
+    }
+
+    #[test]
+    fn test_avoid() {
+        let star_map = grid();
+        let smart_gates_map = SmartGatesMap::new();
+        // With the centre and the 7 -> 8 gate avoided, only the top-right
+        // way around is left
+        let gate = star_map.connections(7).find(|c| c.target == 8).unwrap().id;
+        for algorithm in [PathAlgorithm::Astar, PathAlgorithm::Bidirectional] {
+            let mut options = options(PathOptimize::Distance);
+            options.algorithm = algorithm;
+            options.avoid = Avoid::from_system_ids(&[30000004], &[gate]).unwrap();
+            let result = calc_path(
+                &star_map,
+                &smart_gates_map,
+                &star_map[&0],
+                &star_map[&8],
+                &options,
+            );
+            assert!(matches!(result.status, PathResultStatus::Found));
+            let systems: Vec<u16> = result
+                .path
+                .iter()
+                .map(|c| tools::system_id_to_u16(c.target).unwrap())
+                .collect();
+            assert_eq!(systems, vec![1, 2, 5, 8], "{:?}", algorithm);
+        }
+        // Would alias system 4
+        assert!(Avoid::from_system_ids(&[4 + 474 * 65536], &[]).is_err());
+    }
+
+    #[test]
+    fn test_units() {
+        let star_map = line(true);
+        let smart_gates_map = SmartGatesMap::new();
+        let search = |unit: DistanceUnit| {
+            let mut options = options(PathOptimize::Distance);
+            options.unit = unit;
+            calc_path(
+                &star_map,
+                &smart_gates_map,
+                &star_map[&0],
+                &star_map[&1],
+                &options,
+            )
+        };
+        let ly = search(DistanceUnit::LightYear);
+        let km = search(DistanceUnit::Kilometer);
+        assert_eq!(ly.path[0].distance, 10.0);
+        assert!((km.path[0].distance / 9.4607e13 - 1.0).abs() < 1e-4);
+        assert_eq!(km.unit, DistanceUnit::Kilometer);
+        // Only the results change, not the search
+        assert_eq!(ly.stats.cost, km.stats.cost);
+    }
+
+    #[test]
+    fn test_tank() {
+        let star_map = line(false);
+        let smart_gates_map = SmartGatesMap::new();
+        let mut options = options(PathOptimize::Distance);
+        options.jump_distance = 50;
+        let mut search = |capacity: f64, refuel_systems: &[SolarSystemId]| {
+            options.tank = Some(Tank {
+                capacity,
+                fuel: capacity,
+                refuel_systems: refuel_systems.iter().copied().collect(),
+            });
+            calc_path(
+                &star_map,
+                &smart_gates_map,
+                &star_map[&0],
+                &star_map[&4],
+                &options,
+            )
+        };
+
+        let result = search(45.0, &[]);
+        assert_eq!(result.status, PathResultStatus::Found);
+        assert_eq!(result.stats.cost, 4040);
+        assert!(result.refuels.is_empty());
+        assert_eq!(result.fuel_left, Some(4.6));
+
+        let result = search(25.0, &[]);
+        assert_eq!(result.status, PathResultStatus::NotFound);
+
+        let result = search(25.0, &[2]);
+        assert_eq!(result.status, PathResultStatus::Found);
+        assert_eq!(result.stats.cost, 4040);
+        assert_eq!(result.refuels, vec![tools::u16_to_system_id(2)]);
+        assert_eq!(result.fuel_left, Some(4.8));
+
+        // Refuel systems out of the known ranges would alias another system
+        let tank = |refuel_systems: Vec<u32>| {
+            FuelTank {
+                capacity: 25.0,
+                fuel: None,
+                refuel_systems,
+            }
+            .tank()
+        };
+        assert!(tank(vec![30000002]).unwrap().refuel_systems.contains(&2));
+        assert!(tank(vec![12 + 474 * 65536]).is_err());
+
+        // The tank is only tracked forward, along the best route
+        assert!(options.validate(0).is_ok());
+        assert!(options.validate(1).is_err());
+        options.algorithm = PathAlgorithm::Bidirectional;
+        assert!(options.validate(0).is_err());
+    }
+
+    #[test]
+    fn test_itinerary_tank() {
+        // Leaving half full from a refuel system: the first leg does not
+        // need to refuel, but the second one only works with a full tank
+        let star_map = line(false);
+        let smart_gates_map = SmartGatesMap::new();
+        let mut options = options(PathOptimize::Distance);
+        options.jump_distance = 50;
+        options.tank = Some(Tank {
+            capacity: 25.0,
+            fuel: 15.0,
+            refuel_systems: [0].into_iter().collect(),
+        });
+        let stops = [&star_map[&0], &star_map[&1], &star_map[&2]];
+        let result = calc_itinerary(&star_map, &smart_gates_map, &stops, &options);
+        assert_eq!(result.status, PathResultStatus::Found);
+        assert_eq!(result.legs.len(), 2);
+        assert_eq!(
+            result.path.last().unwrap().target,
+            tools::u16_to_system_id(2)
+        );
+        let leg = calc_path(&star_map, &smart_gates_map, stops[0], stops[1], &options);
+        assert_eq!(leg.refuels, vec![tools::u16_to_system_id(0)]);
+        assert_eq!(leg.fuel_left, Some(14.9));
+    }
+
+    #[test]
+    fn test_one_way() {
+        // A one-way link from 0 to 4, over stars too far apart to jump
+        let mut entries = line(false).entries();
+        let first = entries.iter_mut().find(|entry| entry.star.id == 0).unwrap();
+        first.connections.push(Connection {
+            id: 7,
+            conn_type: ConnType::OneWay,
+            jump_type: 0,
+            distance: 40.4,
+            target: 4,
+        });
+        let star_map = StarMap::new(entries, &BuildSource::default()).unwrap();
+        let incoming: Vec<(ConnectionId, SolarSystemId)> = star_map
+            .incoming(4)
+            .iter()
+            .map(|c| (c.id, c.target))
+            .collect();
+        assert_eq!(incoming, vec![(7, 0)]);
+        assert!(star_map.incoming(0).is_empty());
+        let smart_gates_map = SmartGatesMap::new();
+        for algorithm in [PathAlgorithm::Astar, PathAlgorithm::Bidirectional] {
+            let mut options = options(PathOptimize::Fuel);
+            options.jump_distance = 5;
+            options.algorithm = algorithm;
+            let search = |from: SolarSystemId, to: SolarSystemId| {
+                calc_path(
+                    &star_map,
+                    &smart_gates_map,
+                    &star_map[&from],
+                    &star_map[&to],
+                    &options,
+                )
             };
+            let result = search(0, 4);
+            assert_eq!(result.status, PathResultStatus::Found);
+            assert_eq!(result.path.len(), 1);
+            assert_eq!(result.path[0].conn_type, ConnType::OneWay);
+            assert_eq!(result.stats.cost, 100);
+            assert_eq!(result.path[0].jump_type, Some(0));
+            assert_eq!(search(4, 0).status, PathResultStatus::NotFound);
+            // The weight of one-way links scales their cost
+            let mut weighted = options.clone();
+            weighted.weights.one_way = Some(2.5);
+            let result = calc_path(
+                &star_map,
+                &smart_gates_map,
+                &star_map[&0],
+                &star_map[&4],
+                &weighted,
+            );
+            assert_eq!(result.stats.cost, 250);
         }
-        astar::PathFindResult::Timeout(stats) => {
-            return PathResult {
-                status: PathResultStatus::Timeout,
-                path: vec![],
-                stats: PathResultStats {
-                    cost: 0,
-                    total_time: stats.total_time.as_millis(),
-                    successors_spend: stats.successors_spend.as_millis(),
-                    loop_spend: stats.loop_spend.as_millis(),
-                    visited: stats.visited,
-                },
+    }
+
+    #[test]
+    fn test_avoid_jump() {
+        let star_map = line(false);
+        let smart_gates_map = SmartGatesMap::new();
+        let jump = star_map
+            .jumps(&star_map[&0], 50)
+            .into_iter()
+            .find(|c| c.target == 4)
+            .unwrap();
+        assert!((jump.distance - 40.4).abs() < 0.01);
+        // Out of range, even though it is less than a light-year too long
+        assert!(star_map
+            .jumps(&star_map[&0], 40)
+            .iter()
+            .all(|c| c.target != 4));
+        assert_eq!(star_map.jump_systems(jump.id), Some((0, 4)));
+        let back = star_map.reverse_jump_id(jump.id).unwrap();
+        assert_eq!(star_map.jump_systems(back), Some((4, 0)));
+        for algorithm in [PathAlgorithm::Astar, PathAlgorithm::Bidirectional] {
+            let mut options = options(PathOptimize::Hops);
+            options.jump_distance = 50;
+            options.algorithm = algorithm;
+            let search = |options: &PathOptions| {
+                calc_path(
+                    &star_map,
+                    &smart_gates_map,
+                    &star_map[&0],
+                    &star_map[&4],
+                    options,
+                )
             };
+            assert_eq!(search(&options).stats.cost, 100, "{:?}", algorithm);
+            options.avoid.connections.insert(jump.id);
+            assert_eq!(search(&options).stats.cost, 200, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_avoid_region() {
+        let star_map = grid();
+        let smart_gates_map = SmartGatesMap::new();
+        let mut options = options(PathOptimize::Hops);
+        let search = |options: &PathOptions, end: SolarSystemId| {
+            calc_path(
+                &star_map,
+                &smart_gates_map,
+                &star_map[&0],
+                &star_map[&end],
+                options,
+            )
+        };
+        let result = search(&options, 2);
+        assert_eq!(result.regions, vec![10000000, 10000001, 10000002]);
+        assert_eq!(result.path[0].target_region, Some(10000001));
+        assert_eq!(result.path[0].target_constellation, Some(20000001));
+
+        options.avoid = Avoid::default().with_regions(&star_map, &[10000001]);
+        assert_eq!(search(&options, 2).status, PathResultStatus::NotFound);
+        let result = search(&options, 6);
+        assert_eq!(result.stats.cost, 200);
+        assert_eq!(result.regions, vec![10000000]);
+    }
+
+    #[test]
+    fn test_weighted() {
+        let star_map = line(true);
+        let smart_gates_map = SmartGatesMap::new();
+        // (jump, gate, hop) weights and the cost of the best route from
+        // one end of the line to the other
+        let cases = [
+            ((1.0, 0.0, 0.0), 0),
+            ((1.0, 5.0, 0.0), 2000),
+            ((1.0, 15.0, 0.0), 4040),
+            ((1.0, 15.0, 10.0), 5040),
+            ((0.0, 1.0, 1.0), 100),
+            ((0.25, 2.0, 3.0), 1310),
+        ];
+        for ((jump, gate, hop), expected) in cases {
+            for algorithm in [PathAlgorithm::Astar, PathAlgorithm::Bidirectional] {
+                let mut options = options(PathOptimize::Weighted);
+                options.jump_distance = 50;
+                options.algorithm = algorithm;
+                options.weights = CostWeights {
+                    jump,
+                    gate,
+                    smart_gate: gate,
+                    special: gate,
+                    hop,
+                    ..Default::default()
+                };
+                let result = calc_path(
+                    &star_map,
+                    &smart_gates_map,
+                    &star_map[&0],
+                    &star_map[&4],
+                    &options,
+                );
+                assert_eq!(
+                    result.stats.cost, expected,
+                    "{:?} {:?}",
+                    options.weights, algorithm
+                );
+            }
         }
     }
 }
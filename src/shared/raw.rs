@@ -1,7 +1,42 @@
 use std::collections::HashMap;
 use serde::{Deserialize, Deserializer};
-use serde::de::{self, Visitor, SeqAccess};
+use serde::de::{self, DeserializeOwned, Visitor, SeqAccess};
 use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use flate2::read::GzDecoder;
+use log::info;
+
+/// Open a file for streaming, decompressed when its name ends with `.gz`
+fn open(path: &Path) -> anyhow::Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(path)?);
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Parse a JSON file as it is read, without holding the whole text
+fn read_json<T: DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    serde_json::from_reader(open(path)?).with_context(|| format!("Cannot read {}", path.display()))
+}
+
+// Files of a frontier-static-data extraction, each one may be gzip compressed
+const SOLAR_SYSTEMS_FILE: &str = "solarsystems.json";
+const JUMPS_FILE: &str = "jumps.json";
+const CONSTELLATIONS_FILE: &str = "constellations.json";
+const REGIONS_FILE: &str = "regions.json";
+
+/// A file of an extraction directory, plain or compressed
+fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    [dir.join(name), dir.join(format!("{}.gz", name))]
+        .into_iter()
+        .find(|path| path.is_file())
+}
 
 // ====================================================================
 // Data structures for the starmap pickle extracted from the client
@@ -15,12 +50,96 @@ pub struct RawStarMap {
 }
 
 impl RawStarMap {
-    pub fn from_file(file: &str) -> Self {
-        let file = std::fs::read_to_string(file).unwrap();
-        serde_json::from_str(&file).unwrap()
+    /// A single JSON file with the jumps and the solar systems, or an
+    /// extraction directory
+    pub fn from_path(path: &str) -> anyhow::Result<Self> {
+        if Path::new(path).is_dir() {
+            Self::from_directory(Path::new(path))
+        } else {
+            read_json(Path::new(path))
+        }
+    }
+
+    /// Read the files of a frontier-static-data extraction: the solar
+    /// systems and the jumps, and optionally the constellations and the
+    /// regions. These fill in the constellation and region of the systems
+    /// that do not tell them.
+    pub fn from_directory(dir: &Path) -> anyhow::Result<Self> {
+        let required = |name: &str| {
+            find_file(dir, name).ok_or_else(|| anyhow::anyhow!("No {} in {}", name, dir.display()))
+        };
+        info!("Reading solar systems");
+        let mut solar_systems: HashMap<String, RawSolarSystem> =
+            read_json(&required(SOLAR_SYSTEMS_FILE)?)?;
+        info!("Reading jumps");
+        let jumps = read_json(&required(JUMPS_FILE)?)?;
+
+        let regions: HashMap<u32, RawRegion> = match find_file(dir, REGIONS_FILE) {
+            Some(path) => read_json(&path)?,
+            None => HashMap::new(),
+        };
+        let region_of: HashMap<u32, u32> = regions
+            .iter()
+            .flat_map(|(region, raw)| raw.constellation_ids.iter().map(move |c| (*c, *region)))
+            .collect();
+        if let Some(path) = find_file(dir, CONSTELLATIONS_FILE) {
+            info!("Reading constellations");
+            let constellations: HashMap<u32, RawConstellation> = read_json(&path)?;
+            for (id, constellation) in constellations {
+                let region = match constellation.region_id {
+                    0 => region_of.get(&id).copied().unwrap_or(0),
+                    region => region,
+                };
+                for system in &constellation.solar_system_ids {
+                    if let Some(system) = solar_systems.get_mut(&system.to_string()) {
+                        if system.constellation_id == 0 {
+                            system.constellation_id = id;
+                        }
+                        if system.region_id == 0 {
+                            system.region_id = region;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(RawStarMap {
+            jumps,
+            solar_systems,
+        })
+    }
+
+    /// The files a star map is built from, in a fixed order
+    pub fn source_files(path: &str) -> anyhow::Result<Vec<PathBuf>> {
+        let path = Path::new(path);
+        if !path.is_dir() {
+            return Ok(vec![path.to_path_buf()]);
+        }
+        Ok([
+            SOLAR_SYSTEMS_FILE,
+            JUMPS_FILE,
+            CONSTELLATIONS_FILE,
+            REGIONS_FILE,
+        ]
+        .into_iter()
+        .filter_map(|name| find_file(path, name))
+        .collect())
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RawConstellation {
+    #[serde(rename(deserialize = "regionID"), default)]
+    pub region_id: u32,
+    #[serde(rename(deserialize = "solarSystemIDs"), default)]
+    pub solar_system_ids: Vec<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RawRegion {
+    #[serde(rename(deserialize = "constellationIDs"), default)]
+    pub constellation_ids: Vec<u32>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RawJump {
     #[serde(rename(deserialize = "fromSystemID"))]
@@ -29,12 +148,19 @@ pub struct RawJump {
     pub jump_type: u8,
     #[serde(rename(deserialize = "toSystemID"))]
     pub to_system_id: u32,
+    /// Only travelled from `from_system_id`, absent from most of the data
+    #[serde(rename(deserialize = "oneWay"), default)]
+    pub one_way: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct RawSolarSystem {
     #[serde(deserialize_with = "deserialize_center")]
     pub center: [f64; 3],
+    #[serde(rename(deserialize = "constellationID"), default)]
+    pub constellation_id: u32,
+    #[serde(rename(deserialize = "regionID"), default)]
+    pub region_id: u32,
 }
 
 fn deserialize_center<'de, D>(deserializer: D) -> Result<[f64; 3], D::Error>
@@ -55,14 +181,14 @@ where
             A: SeqAccess<'de>,
         {
             let mut result = [0.0; 3];
-            for i in 0..3 {
+            for slot in result.iter_mut() {
                 let value = seq.next_element::<serde_json::Value>()?;
                 match value {
                     Some(serde_json::Value::Number(n)) => {
-                        result[i] = n.as_f64().ok_or_else(|| de::Error::custom("invalid number"))?;
+                        *slot = n.as_f64().ok_or_else(|| de::Error::custom("invalid number"))?;
                     }
                     Some(serde_json::Value::String(s)) => {
-                        result[i] = s.parse::<f64>().map_err(de::Error::custom)?;
+                        *slot = s.parse::<f64>().map_err(de::Error::custom)?;
                     }
                     _ => return Err(de::Error::custom("expected number or string")),
                 }
@@ -80,4 +206,71 @@ pub struct RawStar {
     pub solar_system_id: u64,
     #[serde(rename(deserialize = "solarSystemName"))]
     pub solar_system_name: String,
-}
\ No newline at end of file
+}
+
+impl RawStar {
+    /// A list of solar systems with their names
+    pub fn from_file(file: &str) -> anyhow::Result<Vec<Self>> {
+        read_json(Path::new(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    #[test]
+    fn test_from_directory() {
+        let dir = std::env::temp_dir().join(format!("frontier-static-data-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(SOLAR_SYSTEMS_FILE),
+            r#"{"30000001": {"center": [0, 0, 0]},
+                "30000002": {"center": ["1e16", 0, 0], "constellationID": 20000009}}"#,
+        )
+        .unwrap();
+        let mut jumps = GzEncoder::new(
+            File::create(dir.join(format!("{}.gz", JUMPS_FILE))).unwrap(),
+            Compression::default(),
+        );
+        jumps
+            .write_all(br#"[{"fromSystemID": 30000001, "jumpType": 0, "toSystemID": 30000002}]"#)
+            .unwrap();
+        jumps.finish().unwrap();
+        std::fs::write(
+            dir.join(CONSTELLATIONS_FILE),
+            r#"{"20000001": {"solarSystemIDs": [30000001, 30000002]}}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join(REGIONS_FILE),
+            r#"{"10000001": {"constellationIDs": [20000001]}}"#,
+        )
+        .unwrap();
+
+        let raw_star_map = RawStarMap::from_path(dir.to_str().unwrap()).unwrap();
+        assert_eq!(raw_star_map.jumps.len(), 1);
+        let system = &raw_star_map.solar_systems["30000001"];
+        assert_eq!(
+            (system.constellation_id, system.region_id),
+            (20000001, 10000001)
+        );
+        // What the systems tell is kept
+        let system = &raw_star_map.solar_systems["30000002"];
+        assert_eq!(
+            (system.constellation_id, system.region_id),
+            (20000009, 10000001)
+        );
+        let files = RawStarMap::source_files(dir.to_str().unwrap()).unwrap();
+        assert_eq!(files.len(), 4);
+        assert!(files[1].ends_with("jumps.json.gz"));
+
+        std::fs::remove_file(dir.join(SOLAR_SYSTEMS_FILE)).unwrap();
+        assert!(RawStarMap::from_path(dir.to_str().unwrap()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
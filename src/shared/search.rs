@@ -11,18 +11,49 @@ use super::astar;
 use super::data::*;
 use super::tools;
 
-pub fn near(star_map: &HashMap<SolarSystemId, Star>, star: &Star, distance: u16) -> NearResult {
+/// Stars at most `distance` light-years away, with their distance in `unit`
+pub fn near(star_map: &StarMap, star: &Star, distance: u16, unit: DistanceUnit) -> NearResult {
     NearResult {
-        connections: star
-            .connections
-            .iter()
-            .filter(|c| c.conn_type == ConnType::Jump)
-            .take_while(|c| c.distance <= distance)
+        connections: star_map
+            .jumps(star, distance)
+            .into_iter()
             .map(|c| PathResultConnection {
                 conn_type: c.conn_type.clone(),
-                distance: c.distance,
+                jump_type: None,
+                distance: unit.convert_light_years(c.distance),
                 target: tools::u16_to_system_id(c.target),
+                target_name: star_map.name(c.target).map(String::from),
+                target_constellation: star_map[&c.target].constellation(),
+                target_region: star_map[&c.target].region(),
                 id: c.id,
+                fuel: None,
+            })
+            .collect(),
+        unit,
+    }
+}
+
+/// Systems of a constellation and/or a region, by id
+pub fn systems(
+    star_map: &StarMap,
+    constellation: Option<u32>,
+    region: Option<u32>,
+) -> SystemsResult {
+    let mut stars: Vec<&Star> = star_map
+        .stars()
+        .iter()
+        .filter(|star| constellation.is_none() || star.constellation() == constellation)
+        .filter(|star| region.is_none() || star.region() == region)
+        .collect();
+    stars.sort_unstable_by_key(|star| star.id);
+    SystemsResult {
+        systems: stars
+            .into_iter()
+            .map(|star| SystemInfo {
+                id: tools::u16_to_system_id(star.id),
+                name: star_map.name(star.id).map(String::from),
+                constellation: star.constellation(),
+                region: star.region(),
             })
             .collect(),
     }
@@ -0,0 +1,153 @@
+use uom::si::f64::*;
+use uom::si::length::meter;
+
+use super::data::*;
+
+/// k-d tree over star coordinates, answering "which stars are within this
+/// distance" without comparing every pair of stars.
+///
+/// The tree is the star list itself, once `arrange`d: the middle star of
+/// any range is the node splitting that range, on x, y and z in turn as the
+/// tree gets deeper. It can then be searched straight from the star map
+/// file.
+#[derive(Debug, Clone, Copy)]
+pub struct SpatialIndex<'a> {
+    stars: &'a [Star],
+}
+
+impl<'a> SpatialIndex<'a> {
+    /// The stars must have been arranged with `arrange`
+    pub fn new(stars: &'a [Star]) -> Self {
+        SpatialIndex { stars }
+    }
+
+    pub fn len(&self) -> usize {
+        self.stars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stars.is_empty()
+    }
+
+    /// Positions in the star list of the stars at most `radius` away from
+    /// `star`, the star itself excluded, with their distance. The order
+    /// follows the tree, not the distance.
+    pub fn within(&self, star: &Star, radius: Length) -> Vec<(usize, Length)> {
+        let mut found = Vec::new();
+        let radius = radius.get::<meter>();
+        self.search(
+            0,
+            self.stars.len(),
+            0,
+            &position(star),
+            radius * radius,
+            &mut found,
+        );
+        found
+            .into_iter()
+            .filter(|(i, _)| self.stars[*i].id != star.id)
+            .map(|(i, squared)| (i, Length::new::<meter>(squared.sqrt())))
+            .collect()
+    }
+
+    fn search(
+        &self,
+        start: usize,
+        end: usize,
+        depth: usize,
+        center: &[f64; 3],
+        squared_radius: f64,
+        found: &mut Vec<(usize, f64)>,
+    ) {
+        if start >= end {
+            return;
+        }
+        let mid = start + (end - start) / 2;
+        let point = position(&self.stars[mid]);
+        let squared: f64 = (0..3)
+            .map(|axis| (point[axis] - center[axis]).powi(2))
+            .sum();
+        if squared <= squared_radius {
+            found.push((mid, squared));
+        }
+        let axis = depth % 3;
+        let offset = center[axis] - point[axis];
+        let (near, far) = if offset < 0.0 {
+            ((start, mid), (mid + 1, end))
+        } else {
+            ((mid + 1, end), (start, mid))
+        };
+        self.search(near.0, near.1, depth + 1, center, squared_radius, found);
+        // The other side can only hold matches if the splitting plane is close enough
+        if offset * offset <= squared_radius {
+            self.search(far.0, far.1, depth + 1, center, squared_radius, found);
+        }
+    }
+}
+
+fn position(star: &Star) -> [f64; 3] {
+    [star.x, star.y, star.z]
+}
+
+/// Reorder the stars into a tree. The same stars always give the same
+/// tree, whatever their order.
+pub fn arrange(stars: &mut [Star]) {
+    stars.sort_unstable_by_key(|star| star.id);
+    build(stars, 0);
+}
+
+/// Arrange the stars so that the middle of every range splits it on the
+/// axis of its depth
+fn build(stars: &mut [Star], depth: usize) {
+    if stars.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    let mid = stars.len() / 2;
+    stars.select_nth_unstable_by(mid, |a, b| position(a)[axis].total_cmp(&position(b)[axis]));
+    let (left, right) = stars.split_at_mut(mid);
+    build(left, depth + 1);
+    build(&mut right[1..], depth + 1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_within_matches_brute_force() {
+        // Deterministic pseudo-random stars in a 1000 m cube
+        let mut seed: u64 = 42;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as f64 / (1u64 << 31) as f64 * 1000.0
+        };
+        let stars: Vec<Star> = (0..500)
+            .map(|id| Star::new(id, next(), next(), next()))
+            .collect();
+        let mut arranged = stars.clone();
+        arrange(&mut arranged);
+        let index = SpatialIndex::new(&arranged);
+        assert_eq!(index.len(), stars.len());
+
+        for radius in [0.0, 50.0, 150.0, 2000.0] {
+            let radius = Length::new::<meter>(radius);
+            for star in &stars {
+                let mut found: Vec<SolarSystemId> = index
+                    .within(star, radius)
+                    .iter()
+                    .map(|(i, _)| arranged[*i].id)
+                    .collect();
+                found.sort();
+                let expected: Vec<SolarSystemId> = stars
+                    .iter()
+                    .filter(|other| other.id != star.id && star.distance(other) <= radius)
+                    .map(|other| other.id)
+                    .collect();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Star maps and options shared by the unit tests
+
+use std::time::Duration;
+
+use super::astar::SearchLimits;
+use super::data::*;
+
+/// One light-year in meters, the unit of star coordinates
+pub const LY: f64 = 9.4607e15;
+
+/// Jumps disabled, default weights and a generous timeout
+pub fn options(optimize: PathOptimize) -> PathOptions {
+    PathOptions {
+        jump_distance: 0,
+        optimize,
+        weights: CostWeights::default(),
+        algorithm: PathAlgorithm::Astar,
+        avoid: Avoid::default(),
+        ship: None,
+        tank: None,
+        limits: SearchLimits::with_timeout(Duration::from_secs(10)),
+        unit: DistanceUnit::LightYear,
+    }
+}
+
+/// A 3x3 grid of stars 10 ly apart with gates between neighbours, each
+/// column is a region
+pub fn grid() -> StarMap {
+    let mut conn_id = 1;
+    let stars = (0..9u16)
+        .map(|id| {
+            let star = Star::new(
+                id,
+                (id % 3) as f64 * 10.0 * LY,
+                0.0,
+                (id / 3) as f64 * 10.0 * LY,
+            )
+            .with_hierarchy(20000000 + id as u32, 10000000 + (id % 3) as u32);
+            let mut neighbours = vec![];
+            if id % 3 < 2 {
+                neighbours.push(id + 1);
+            }
+            if id % 3 > 0 {
+                neighbours.push(id - 1);
+            }
+            if id / 3 < 2 {
+                neighbours.push(id + 3);
+            }
+            if id >= 3 {
+                neighbours.push(id - 3);
+            }
+            let connections = neighbours
+                .into_iter()
+                .map(|target| {
+                    conn_id += 1;
+                    Connection {
+                        id: conn_id - 1,
+                        conn_type: ConnType::Gate,
+                        jump_type: 0,
+                        distance: 10.0,
+                        target,
+                    }
+                })
+                .collect();
+            StarEntry {
+                star,
+                name: String::new(),
+                connections,
+            }
+        })
+        .collect();
+    StarMap::new(stars, &BuildSource::default()).unwrap()
+}
+
+/// 5 stars on a line a bit more than 10 ly apart, so that jumps
+/// between them are 10, 20, 30 and 40 ly long once rounded down, and
+/// optionally gates between neighbours
+pub fn line(gates: bool) -> StarMap {
+    let stars = (0..5u16)
+        .map(|id| {
+            let star = Star::new(id, id as f64 * 10.1 * LY, 0.0, 0.0);
+            let connections = [id.wrapping_sub(1), id + 1]
+                .into_iter()
+                .filter(|&target| gates && target < 5)
+                .map(|target| Connection {
+                    id: (100 + id * 5 + target) as ConnectionId,
+                    conn_type: ConnType::Gate,
+                    jump_type: 0,
+                    distance: 10.0,
+                    target,
+                })
+                .collect();
+            StarEntry {
+                star,
+                name: String::new(),
+                connections,
+            }
+        })
+        .collect();
+    StarMap::new(stars, &BuildSource::default()).unwrap()
+}
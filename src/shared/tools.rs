@@ -9,6 +9,14 @@ pub fn system_id_to_u16(system_id: u32) -> Result<u16, std::num::ParseIntError>
     Ok(converted as u16)
 }
 
+/// Star map id of a system, unless the id is outside the ranges a star map
+/// can store and would alias another system
+pub fn stored_system_id(system_id: u32) -> Option<u16> {
+    system_id_to_u16(system_id)
+        .ok()
+        .filter(|&converted| u16_to_system_id(converted) == system_id)
+}
+
 pub fn u16_to_system_id(value: u16) -> u32 {
     match value {
         0..=29999 => 30000000 + value as u32,
@@ -27,6 +35,8 @@ mod tests {
         assert_eq!(system_id_to_u16(30018456).unwrap(), 18456);
         assert_eq!(system_id_to_u16(32001234).unwrap(), 31234);
         assert_eq!(system_id_to_u16(34000004).unwrap(), 40004);
+        assert_eq!(stored_system_id(30018456), Some(18456));
+        assert_eq!(stored_system_id(31000005), None);
     }
 
     #[test]
@@ -0,0 +1,430 @@
+use std::time::Instant;
+
+use rayon::prelude::*;
+
+use log::info;
+
+use super::data::*;
+use super::path;
+use super::tools;
+
+/// Up to this many stops (start included) the order is solved exactly,
+/// beyond that a local search heuristic is used
+pub const EXACT_LIMIT: usize = 12;
+
+/// Cost of a leg that cannot be travelled
+const UNREACHABLE: i64 = i64::MAX;
+
+/// Find the cheapest order to visit all the stops, starting from the first
+/// one and optionally coming back to it, and plan the routes between them.
+///
+/// Every pair of stops is searched once to build the cost matrix, then the
+/// order is solved on that matrix. Pairs are searched with the fuel at
+/// departure, so with a tank the legs of the chosen order are searched again,
+/// each with the fuel the previous one left.
+pub fn calc_tour(
+    star_map: &StarMap,
+    smart_gates_map: &SmartGatesMap,
+    stops: &[&Star],
+    options: &PathOptions,
+    round_trip: bool,
+) -> TourResult {
+    let n = stops.len();
+    // Nothing comes back to the start on one-way tours
+    let pairs: Vec<(usize, usize)> = (0..n)
+        .flat_map(|i| (0..n).map(move |j| (i, j)))
+        .filter(|&(i, j)| i != j && (round_trip || j != 0))
+        .collect();
+    info!("Searching {} routes between {} stops", pairs.len(), n);
+    let mut routes: Vec<Vec<Option<PathResult>>> = (0..n).map(|_| Vec::new()).collect();
+    for row in routes.iter_mut() {
+        row.resize_with(n, || None);
+    }
+    let start_time = Instant::now();
+    let results: Vec<(usize, usize, PathResult)> = pairs
+        .par_iter()
+        .map(|&(i, j)| {
+            // Pairs share the limits of the whole tour
+            let options = PathOptions {
+                limits: options.limits.remaining(start_time.elapsed()),
+                ..options.clone()
+            };
+            let result = path::calc_path(star_map, smart_gates_map, stops[i], stops[j], &options);
+            (i, j, result)
+        })
+        .collect();
+    for (i, j, result) in results {
+        routes[i][j] = Some(result);
+    }
+
+    let costs: Vec<Vec<i64>> = routes
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|route| match route {
+                    Some(route) if route.status == PathResultStatus::Found => route.stats.cost,
+                    _ => UNREACHABLE,
+                })
+                .collect()
+        })
+        .collect();
+    let exact = n <= EXACT_LIMIT;
+    let order = if exact {
+        solve_exact(&costs, round_trip)
+    } else {
+        solve_heuristic(&costs, round_trip)
+    };
+    let Some(order) = order else {
+        let timeout = routes
+            .iter()
+            .flatten()
+            .flatten()
+            .any(|route| route.status == PathResultStatus::Timeout);
+        return TourResult {
+            status: if timeout {
+                PathResultStatus::Timeout
+            } else {
+                PathResultStatus::NotFound
+            },
+            order: vec![],
+            exact,
+            path: vec![],
+            regions: vec![],
+            legs: vec![],
+            cost: 0,
+            unreachable: unreachable_stop(&costs, round_trip)
+                .map(|i| tools::u16_to_system_id(stops[i].id)),
+            unit: options.unit,
+        };
+    };
+    let mut visits = order.clone();
+    if round_trip && n > 1 {
+        visits.push(0);
+    }
+    let itinerary = options.tank.as_ref().map(|_| {
+        let stops: Vec<&Star> = visits.iter().map(|&i| stops[i]).collect();
+        let options = PathOptions {
+            limits: options.limits.remaining(start_time.elapsed()),
+            ..options.clone()
+        };
+        path::calc_itinerary(star_map, smart_gates_map, &stops, &options)
+    });
+
+    let mut result = TourResult {
+        status: PathResultStatus::Found,
+        order: visits
+            .iter()
+            .map(|&i| tools::u16_to_system_id(stops[i].id))
+            .collect(),
+        exact,
+        path: vec![],
+        regions: vec![],
+        legs: vec![],
+        cost: 0,
+        unreachable: None,
+        unit: options.unit,
+    };
+    if let Some(itinerary) = itinerary {
+        result.status = itinerary.status;
+        result.path = itinerary.path;
+        result.regions = itinerary.regions;
+        result.legs = itinerary.legs;
+        result.cost = itinerary.cost;
+        return result;
+    }
+    for pair in visits.windows(2) {
+        let leg = routes[pair[0]][pair[1]].take().unwrap(); // Every leg was searched
+        result.legs.push(ItineraryLeg {
+            from: tools::u16_to_system_id(stops[pair[0]].id),
+            to: tools::u16_to_system_id(stops[pair[1]].id),
+            status: leg.status,
+            start: result.path.len(),
+            length: leg.path.len(),
+            stats: leg.stats,
+        });
+        result.cost += costs[pair[0]][pair[1]];
+        result.path.extend(leg.path);
+    }
+    if let Some(&start) = visits.first() {
+        result.regions = path::route_regions(stops[start], &result.path);
+    }
+    result
+}
+
+/// Total cost of visiting the stops in order, saturating on unreachable legs
+fn tour_cost(costs: &[Vec<i64>], order: &[usize], round_trip: bool) -> i64 {
+    let back = match (round_trip, order.last()) {
+        (true, Some(&last)) if order.len() > 1 => costs[last][order[0]],
+        _ => 0,
+    };
+    order
+        .windows(2)
+        .map(|pair| costs[pair[0]][pair[1]])
+        .fold(back, i64::saturating_add)
+}
+
+/// First stop that no found route leads to, or on round trips leaves from
+fn unreachable_stop(costs: &[Vec<i64>], round_trip: bool) -> Option<usize> {
+    let n = costs.len();
+    let into = |j: usize| (0..n).any(|i| i != j && costs[i][j] != UNREACHABLE);
+    let out_of = |j: usize| (0..n).any(|i| i != j && costs[j][i] != UNREACHABLE);
+    if round_trip {
+        (0..n).find(|&j| !into(j) || !out_of(j))
+    } else {
+        (1..n).find(|&j| !into(j))
+    }
+}
+
+/// Orders that miss a leg are not tours
+fn feasible(costs: &[Vec<i64>], order: Vec<usize>, round_trip: bool) -> Option<Vec<usize>> {
+    (tour_cost(costs, &order, round_trip) != UNREACHABLE).then_some(order)
+}
+
+/// Held-Karp dynamic programming, exact but exponential in the number of stops.
+/// The first stop is always the start. None when no order visits every stop.
+fn solve_exact(costs: &[Vec<i64>], round_trip: bool) -> Option<Vec<usize>> {
+    let n = costs.len();
+    if n <= 2 {
+        return feasible(costs, (0..n).collect(), round_trip);
+    }
+    // Stops 1..n are the bits of the mask
+    let m = n - 1;
+    let full = (1usize << m) - 1;
+    // best[mask][j]: cheapest way to visit `mask` from the start, ending at stop j + 1
+    let mut best = vec![vec![UNREACHABLE; m]; 1 << m];
+    let mut parent = vec![vec![usize::MAX; m]; 1 << m];
+    for j in 0..m {
+        best[1 << j][j] = costs[0][j + 1];
+    }
+    for mask in 1..=full {
+        for j in 0..m {
+            let cost = best[mask][j];
+            if mask & (1 << j) == 0 || cost == UNREACHABLE {
+                continue;
+            }
+            for k in 0..m {
+                if mask & (1 << k) != 0 {
+                    continue;
+                }
+                let next = mask | (1 << k);
+                let new_cost = cost.saturating_add(costs[j + 1][k + 1]);
+                if new_cost < best[next][k] {
+                    best[next][k] = new_cost;
+                    parent[next][k] = j;
+                }
+            }
+        }
+    }
+    let back = |j: usize| if round_trip { costs[j + 1][0] } else { 0 };
+    let last = (0..m)
+        .min_by_key(|&j| best[full][j].saturating_add(back(j)))
+        .unwrap();
+    if best[full][last].saturating_add(back(last)) == UNREACHABLE {
+        return None;
+    }
+
+    let mut order = Vec::with_capacity(n);
+    let (mut mask, mut j) = (full, last);
+    while j != usize::MAX {
+        order.push(j + 1);
+        let previous = parent[mask][j];
+        mask &= !(1 << j);
+        j = previous;
+    }
+    order.push(0);
+    order.reverse();
+    Some(order)
+}
+
+/// Nearest neighbour tour improved with 2-opt and Or-opt moves until no
+/// move makes it cheaper. The first stop is always the start. None when
+/// the best order found still misses a leg.
+fn solve_heuristic(costs: &[Vec<i64>], round_trip: bool) -> Option<Vec<usize>> {
+    let n = costs.len();
+    let mut order = vec![0];
+    let mut left: Vec<usize> = (1..n).collect();
+    while !left.is_empty() {
+        let last = *order.last().unwrap();
+        let (i, _) = left
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &stop)| costs[last][stop])
+            .unwrap();
+        order.push(left.swap_remove(i));
+    }
+
+    let mut best = tour_cost(costs, &order, round_trip);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        // 2-opt: reverse a section of the tour
+        for i in 1..n {
+            for j in i + 1..n {
+                order[i..=j].reverse();
+                let cost = tour_cost(costs, &order, round_trip);
+                if cost < best {
+                    best = cost;
+                    improved = true;
+                } else {
+                    order[i..=j].reverse();
+                }
+            }
+        }
+        // Or-opt: move a section of up to 3 stops somewhere else
+        for len in 1..=3 {
+            for i in 1..n.saturating_sub(len - 1) {
+                for j in 1..=n - len {
+                    if j == i {
+                        continue;
+                    }
+                    let mut candidate = order.clone();
+                    let section: Vec<usize> = candidate.drain(i..i + len).collect();
+                    candidate.splice(j..j, section);
+                    let cost = tour_cost(costs, &candidate, round_trip);
+                    if cost < best {
+                        best = cost;
+                        order = candidate;
+                        improved = true;
+                    }
+                }
+            }
+        }
+    }
+    feasible(costs, order, round_trip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::test_utils::{self, LY};
+    use super::*;
+
+    /// Stops on a line at the given positions, travel cost is the gap
+    fn line(positions: &[i64]) -> Vec<Vec<i64>> {
+        positions
+            .iter()
+            .map(|a| positions.iter().map(|b| (a - b).abs()).collect())
+            .collect()
+    }
+
+    fn brute_force(costs: &[Vec<i64>], round_trip: bool) -> i64 {
+        fn permute(order: &mut Vec<usize>, k: usize, f: &mut dyn FnMut(&[usize])) {
+            if k == order.len() {
+                return f(order);
+            }
+            for i in k..order.len() {
+                order.swap(k, i);
+                permute(order, k + 1, f);
+                order.swap(k, i);
+            }
+        }
+        let mut best = UNREACHABLE;
+        let mut order: Vec<usize> = (0..costs.len()).collect();
+        permute(&mut order, 1, &mut |o| {
+            best = best.min(tour_cost(costs, o, round_trip))
+        });
+        best
+    }
+
+    #[test]
+    fn test_solve_exact() {
+        let costs = line(&[0, 50, -20, 30, 10, -40, 70]);
+        for round_trip in [false, true] {
+            let order = solve_exact(&costs, round_trip).unwrap();
+            assert_eq!(order[0], 0);
+            assert_eq!(order.len(), costs.len());
+            assert_eq!(
+                tour_cost(&costs, &order, round_trip),
+                brute_force(&costs, round_trip)
+            );
+        }
+        assert_eq!(
+            tour_cost(&costs, &solve_exact(&costs, true).unwrap(), true),
+            220
+        );
+    }
+
+    #[test]
+    fn test_solve_exact_asymmetric() {
+        // Going "up" is cheap, coming back is expensive
+        let costs: Vec<Vec<i64>> = (0..5)
+            .map(|i| (0..5).map(|j| if j > i { 1 } else { 100 }).collect())
+            .collect();
+        assert_eq!(solve_exact(&costs, false), Some(vec![0, 1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn test_solve_heuristic() {
+        let positions: Vec<i64> = (0..20).map(|i| (i * 37) % 101).collect();
+        let costs = line(&positions);
+        let order = solve_heuristic(&costs, false).unwrap();
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+        // On a line the best open tour from 0 sweeps one side then the other
+        let min = *positions.iter().min().unwrap();
+        let max = *positions.iter().max().unwrap();
+        let optimal = (max - min) + (positions[0] - min).min(max - positions[0]);
+        assert_eq!(tour_cost(&costs, &order, false), optimal);
+    }
+
+    #[test]
+    fn test_isolated_stop() {
+        // Stop 2 is far from the others, every order misses a leg
+        let mut costs = line(&[0, 10, 20, 30]);
+        for row in costs.iter_mut() {
+            row[2] = UNREACHABLE;
+        }
+        for round_trip in [false, true] {
+            assert_eq!(solve_exact(&costs, round_trip), None);
+            assert_eq!(solve_heuristic(&costs, round_trip), None);
+            assert_eq!(unreachable_stop(&costs, round_trip), Some(2));
+        }
+
+        let entries = [0.0, 10.1, 1000.0, 20.2]
+            .iter()
+            .enumerate()
+            .map(|(id, x)| StarEntry {
+                star: Star::new(id as SolarSystemId, x * LY, 0.0, 0.0),
+                name: String::new(),
+                connections: vec![],
+            })
+            .collect();
+        let star_map = StarMap::new(entries, &BuildSource::default()).unwrap();
+        let options = PathOptions {
+            jump_distance: 50,
+            ..test_utils::options(PathOptimize::Distance)
+        };
+        let stops: Vec<&Star> = (0..4).map(|id| &star_map[&id]).collect();
+        let tour = calc_tour(&star_map, &SmartGatesMap::new(), &stops, &options, false);
+        assert_eq!(tour.status, PathResultStatus::NotFound);
+        assert_eq!(tour.unreachable, Some(tools::u16_to_system_id(2)));
+        assert!(tour.order.is_empty() && tour.path.is_empty());
+    }
+
+    #[test]
+    fn test_tank() {
+        // Each leg can be flown with the fuel at departure, but not both
+        let star_map = test_utils::line(false);
+        let mut options = PathOptions {
+            jump_distance: 50,
+            tank: Some(Tank {
+                capacity: 25.0,
+                fuel: 15.0,
+                refuel_systems: Default::default(),
+            }),
+            ..test_utils::options(PathOptimize::Distance)
+        };
+        let stops: Vec<&Star> = (0..3).map(|id| &star_map[&id]).collect();
+        let tour = calc_tour(&star_map, &SmartGatesMap::new(), &stops, &options, false);
+        assert_eq!(tour.status, PathResultStatus::NotFound);
+        assert_eq!(tour.legs.len(), 2);
+        assert_eq!(tour.legs[1].status, PathResultStatus::NotFound);
+
+        // Refuelling on the way makes it feasible
+        options.tank.as_mut().unwrap().refuel_systems.insert(1);
+        let tour = calc_tour(&star_map, &SmartGatesMap::new(), &stops, &options, false);
+        assert_eq!(tour.status, PathResultStatus::Found);
+        assert_eq!(tour.cost, 2020);
+        assert_eq!(tour.path.len(), 2);
+    }
+}
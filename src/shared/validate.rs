@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use serde::Serialize;
+
+use super::raw;
+use super::tools;
+
+/// A record of the raw data the star map cannot be built from, or builds
+/// differently than expected
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum Problem {
+    /// A system whose id is not a number
+    NonNumericId { id: String },
+    /// A system whose id is outside the ranges a star map can store
+    IdOutOfRange { id: String },
+    /// A system with a coordinate that is not a finite number
+    InvalidCenter { id: u32 },
+    /// A jump from or to a system that is not in the data, or was skipped
+    DanglingJump { from: u32, to: u32 },
+    /// A jump between two systems already linked by a previous jump, in
+    /// either direction unless both are one-way
+    DuplicateJump { from: u32, to: u32 },
+    /// A jump of a type other than gates, kept as a special link
+    UnknownJumpType { from: u32, to: u32, jump_type: u8 },
+}
+
+impl Problem {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Problem::NonNumericId { .. } => "non-numeric system id",
+            Problem::IdOutOfRange { .. } => "system id out of range",
+            Problem::InvalidCenter { .. } => "invalid coordinates",
+            Problem::DanglingJump { .. } => "jump to an unknown system",
+            Problem::DuplicateJump { .. } => "duplicate jump",
+            Problem::UnknownJumpType { .. } => "unknown jump type",
+        }
+    }
+
+    /// Warnings do not skip the record, and do not fail a strict build
+    pub fn is_warning(&self) -> bool {
+        matches!(self, Problem::UnknownJumpType { .. })
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::NonNumericId { id } | Problem::IdOutOfRange { id } => {
+                write!(f, "{}: {}", self.kind(), id)
+            }
+            Problem::InvalidCenter { id } => write!(f, "{}: {}", self.kind(), id),
+            Problem::DanglingJump { from, to } | Problem::DuplicateJump { from, to } => {
+                write!(f, "{}: {} -> {}", self.kind(), from, to)
+            }
+            Problem::UnknownJumpType {
+                from,
+                to,
+                jump_type,
+            } => write!(f, "{} {}: {} -> {}", self.kind(), jump_type, from, to),
+        }
+    }
+}
+
+/// What the validation kept and every problem it found, systems by id
+/// then jumps in the order of the raw data
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ValidationReport {
+    pub systems: usize,
+    pub jumps: usize,
+    pub problems: Vec<Problem>,
+}
+
+impl ValidationReport {
+    pub fn is_empty(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Problems that skipped a record
+    pub fn errors(&self) -> impl Iterator<Item = &Problem> {
+        self.problems.iter().filter(|problem| !problem.is_warning())
+    }
+
+    /// Number of skipped records of each kind
+    pub fn summary(&self) -> BTreeMap<&'static str, usize> {
+        count(self.errors())
+    }
+
+    /// Number of warnings of each kind
+    pub fn warnings(&self) -> BTreeMap<&'static str, usize> {
+        count(self.problems.iter().filter(|problem| problem.is_warning()))
+    }
+}
+
+fn count<'a>(problems: impl Iterator<Item = &'a Problem>) -> BTreeMap<&'static str, usize> {
+    let mut summary = BTreeMap::new();
+    for problem in problems {
+        *summary.entry(problem.kind()).or_default() += 1;
+    }
+    summary
+}
+
+/// Check the raw data, and keep only the systems and jumps the star map can
+/// be built from. Jumps of skipped systems are skipped too, jumps of unknown
+/// types are kept with a warning.
+pub fn validate(raw_star_map: raw::RawStarMap) -> (raw::RawStarMap, ValidationReport) {
+    let mut report = ValidationReport::default();
+    let mut ids: Vec<String> = raw_star_map.solar_systems.keys().cloned().collect();
+    ids.sort_unstable();
+    let mut raw_systems = raw_star_map.solar_systems;
+    let mut solar_systems = HashMap::new();
+    for key in ids {
+        let Ok(id) = key.parse::<u64>() else {
+            report.problems.push(Problem::NonNumericId { id: key });
+            continue;
+        };
+        // Other ids would alias another system
+        let Some(id) = u32::try_from(id)
+            .ok()
+            .filter(|&id| tools::stored_system_id(id).is_some())
+        else {
+            report.problems.push(Problem::IdOutOfRange { id: key });
+            continue;
+        };
+        let raw_system = raw_systems.remove(&key).unwrap();
+        if !raw_system.center.iter().all(|c| c.is_finite()) {
+            report.problems.push(Problem::InvalidCenter { id });
+            continue;
+        }
+        solar_systems.insert(key, raw_system);
+    }
+    let known: HashSet<u32> = solar_systems
+        .keys()
+        .map(|key| key.parse().unwrap())
+        .collect();
+
+    let mut linked = HashSet::new();
+    let jumps = raw_star_map
+        .jumps
+        .into_iter()
+        .filter(|jump| {
+            let (from, to) = (jump.from_system_id, jump.to_system_id);
+            let key = if jump.one_way {
+                (from, to, true)
+            } else {
+                (from.min(to), from.max(to), false)
+            };
+            let problem = if !known.contains(&from) || !known.contains(&to) {
+                Problem::DanglingJump { from, to }
+            } else if !linked.insert(key) {
+                Problem::DuplicateJump { from, to }
+            } else if !matches!(jump.jump_type, 0 | 1) {
+                report.problems.push(Problem::UnknownJumpType {
+                    from,
+                    to,
+                    jump_type: jump.jump_type,
+                });
+                return true;
+            } else {
+                return true;
+            };
+            report.problems.push(problem);
+            false
+        })
+        .collect::<Vec<_>>();
+
+    report.systems = solar_systems.len();
+    report.jumps = jumps.len();
+    (
+        raw::RawStarMap {
+            jumps,
+            solar_systems,
+        },
+        report,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn system(center: [f64; 3]) -> raw::RawSolarSystem {
+        raw::RawSolarSystem {
+            center,
+            constellation_id: 0,
+            region_id: 0,
+        }
+    }
+
+    fn jump(from: u32, to: u32, jump_type: u8) -> raw::RawJump {
+        raw::RawJump {
+            from_system_id: from,
+            jump_type,
+            to_system_id: to,
+            one_way: false,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let raw_star_map = raw::RawStarMap {
+            solar_systems: HashMap::from([
+                ("30000001".to_string(), system([0.0; 3])),
+                ("30000002".to_string(), system([1.0, 2.0, 3.0])),
+                ("30000003".to_string(), system([f64::NAN, 0.0, 0.0])),
+                ("J-30000004".to_string(), system([0.0; 3])),
+                ("31000005".to_string(), system([0.0; 3])),
+            ]),
+            jumps: vec![
+                jump(30000001, 30000002, 0),
+                jump(30000002, 30000001, 1),
+                jump(30000001, 30000003, 0),
+                jump(30000001, 30000009, 1),
+                jump(30000001, 30000002, 7),
+                raw::RawJump {
+                    one_way: true,
+                    ..jump(30000002, 30000001, 7)
+                },
+            ],
+        };
+        let (valid, report) = validate(raw_star_map);
+        assert_eq!(valid.solar_systems.len(), 2);
+        assert_eq!(valid.jumps.len(), 2);
+        assert_eq!((report.systems, report.jumps), (2, 2));
+        assert_eq!(report.errors().count(), 7);
+        assert_eq!(
+            report.problems,
+            vec![
+                Problem::InvalidCenter { id: 30000003 },
+                Problem::IdOutOfRange {
+                    id: "31000005".to_string()
+                },
+                Problem::NonNumericId {
+                    id: "J-30000004".to_string()
+                },
+                Problem::DuplicateJump {
+                    from: 30000002,
+                    to: 30000001
+                },
+                Problem::DanglingJump {
+                    from: 30000001,
+                    to: 30000003
+                },
+                Problem::DanglingJump {
+                    from: 30000001,
+                    to: 30000009
+                },
+                Problem::DuplicateJump {
+                    from: 30000001,
+                    to: 30000002
+                },
+                Problem::UnknownJumpType {
+                    from: 30000002,
+                    to: 30000001,
+                    jump_type: 7
+                },
+            ]
+        );
+        assert_eq!(report.summary()["jump to an unknown system"], 2);
+        assert_eq!(report.warnings()["unknown jump type"], 1);
+    }
+}